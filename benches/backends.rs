@@ -0,0 +1,141 @@
+use std::time::Duration;
+
+use criterion::black_box;
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::BenchmarkId;
+use criterion::Criterion;
+use timed_queue::TimedHeap;
+use timed_queue::TimedQueue;
+use timed_queue::TimingWheel;
+
+const SIZES: [u64; 2] = [10_000, 100_000];
+
+/// Pseudo-random expirations within about a minute of millisecond ticks.
+fn expirations(n: u64) -> Vec<u64> {
+    let mut x = 0x9e37_79b9_7f4a_7c15_u64;
+    (0..n)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            x % 60_000
+        })
+        .collect()
+}
+
+fn insert_cancel(c: &mut Criterion) {
+    let mut group = c.benchmark_group("insert_cancel");
+    for n in SIZES {
+        let expirations = expirations(n);
+        group.bench_with_input(
+            BenchmarkId::new("heap", n),
+            &expirations,
+            |b, expirations| {
+                b.iter(|| {
+                    let mut heap = TimedHeap::new();
+                    let keys: Vec<_> = expirations
+                        .iter()
+                        .map(|&expiration| heap.push((), Some(expiration)))
+                        .collect();
+                    for key in keys {
+                        black_box(heap.remove(key));
+                    }
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("wheel", n),
+            &expirations,
+            |b, expirations| {
+                b.iter(|| {
+                    let mut wheel = TimingWheel::new();
+                    let keys: Vec<_> = expirations
+                        .iter()
+                        .map(|&expiration| wheel.push((), Some(expiration)))
+                        .collect();
+                    for key in keys {
+                        black_box(wheel.remove(key));
+                    }
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn insert_pop(c: &mut Criterion) {
+    let mut group = c.benchmark_group("insert_pop");
+    for n in SIZES {
+        let expirations = expirations(n);
+        group.bench_with_input(
+            BenchmarkId::new("heap", n),
+            &expirations,
+            |b, expirations| {
+                b.iter(|| {
+                    let mut heap = TimedHeap::new();
+                    for &expiration in expirations {
+                        heap.push((), Some(expiration));
+                    }
+                    for now in (0..60_000).step_by(100) {
+                        while let Some(item) = heap.pop_due(now) {
+                            black_box(item);
+                        }
+                    }
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("wheel", n),
+            &expirations,
+            |b, expirations| {
+                b.iter(|| {
+                    let mut wheel = TimingWheel::new();
+                    for &expiration in expirations {
+                        wheel.push((), Some(expiration));
+                    }
+                    for now in (0..60_000).step_by(100) {
+                        while let Some(item) = wheel.pop_due(now) {
+                            black_box(item);
+                        }
+                    }
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn queue_enqueue_cancel(c: &mut Criterion) {
+    let mut group = c.benchmark_group("queue_enqueue_cancel");
+    for n in SIZES {
+        let delays: Vec<_> = expirations(n)
+            .into_iter()
+            .map(Duration::from_millis)
+            .collect();
+        let enqueue_cancel = |queue: TimedQueue<()>, delays: &[Duration]| {
+            let handles: Vec<_> = delays
+                .iter()
+                .map(|&delay| queue.enqueue_after((), delay).unwrap())
+                .collect();
+            for handle in handles {
+                black_box(handle.cancel());
+            }
+        };
+        group.bench_with_input(BenchmarkId::new("heap", n), &delays, |b, delays| {
+            b.iter(|| enqueue_cancel(TimedQueue::new(), delays))
+        });
+        group.bench_with_input(BenchmarkId::new("wheel", n), &delays, |b, delays| {
+            b.iter(|| {
+                enqueue_cancel(
+                    TimedQueue::with_timing_wheel(Duration::from_millis(1)),
+                    delays,
+                )
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, insert_cancel, insert_pop, queue_enqueue_cancel);
+criterion_main!(benches);
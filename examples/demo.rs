@@ -1,6 +1,5 @@
 use std::io::BufRead;
 use std::time::Duration;
-use std::time::Instant;
 
 use timed_queue::TimedQueue;
 
@@ -9,7 +8,7 @@ fn put_them(tq: TimedQueue<usize>) {
     for (idx, line) in stdin.lock().lines().enumerate() {
         let line = line.unwrap();
         let dur: u64 = line.parse().unwrap();
-        tq.enqueue(idx, Some(Instant::now() + Duration::from_secs(dur)));
+        tq.enqueue_after(idx, Duration::from_secs(dur)).unwrap();
     }
 }
 
@@ -22,8 +21,7 @@ async fn main() {
         move || put_them(tq)
     });
 
-    loop {
-        let next = tq.dequeue().await.0;
+    while let Some((next, _)) = tq.dequeue().await {
         println!("Fired: {}", next)
     }
 }
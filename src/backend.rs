@@ -0,0 +1,250 @@
+use std::convert::TryFrom;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::clock::add_saturating;
+use crate::Key;
+use crate::TimedHeap;
+use crate::TimingWheel;
+
+/// Where a `TimedQueue` keeps its items.
+pub(crate) enum Backend<T> {
+    Heap(TimedHeap<T, Instant>),
+    Wheel(Wheel<T>),
+}
+
+/// A `TimingWheel` ticking every `resolution` from `origin`. Items keep their exact expiration alongside, to
+/// be returned with them.
+pub(crate) struct Wheel<T> {
+    wheel: TimingWheel<(T, Option<Instant>)>,
+    origin: Instant,
+    resolution: Duration,
+}
+
+impl<T> Wheel<T> {
+    pub(crate) fn with_capacity(origin: Instant, resolution: Duration, capacity: usize) -> Self {
+        Self {
+            wheel: TimingWheel::with_capacity(capacity),
+            origin,
+            resolution,
+        }
+    }
+
+    /// The last tick at or before `instant`.
+    fn tick_floor(&self, instant: Instant) -> u64 {
+        let elapsed = instant.saturating_duration_since(self.origin).as_nanos();
+        u64::try_from(elapsed / self.resolution.as_nanos()).unwrap_or(u64::MAX)
+    }
+
+    /// The first tick at or after `instant`, so that items are never returned early.
+    fn tick_ceil(&self, instant: Instant) -> u64 {
+        tick_ceil(self.origin, self.resolution, instant)
+    }
+
+    fn instant(&self, tick: u64) -> Instant {
+        let nanos = self.resolution.as_nanos() * u128::from(tick);
+        add_saturating(self.origin, duration_from_nanos(nanos))
+    }
+
+    /// The tick to pass to the wheel for `now`.
+    ///
+    /// The wheel only moves forwards: once it has advanced to a tick, items expiring by then count as due. If the
+    /// clock has since gone back (a `WallClock` after a system time step, or `ManualClock::set`), and that would
+    /// have the next item returned before its expiration, the wheel is first rewound to `now`.
+    fn now_tick(&mut self, now: Instant) -> u64 {
+        let tick = self.tick_floor(now);
+        if tick >= self.wheel.elapsed() && now >= self.origin {
+            return tick;
+        }
+        let early = match self.wheel.due_key(tick) {
+            Some(key) => {
+                matches!(self.wheel.get(key), Some((_, Some(expiration))) if *expiration > now)
+            }
+            None => false,
+        };
+        if !early {
+            return tick;
+        }
+        if now < self.origin {
+            // Back by whole ticks, so that expirations still round up to the same instants.
+            let resolution = self.resolution.as_nanos();
+            let behind = self.origin.duration_since(now).as_nanos();
+            let back = duration_from_nanos(behind.div_ceil(resolution) * resolution);
+            if let Some(origin) = self.origin.checked_sub(back) {
+                self.origin = origin;
+            }
+        }
+        let (origin, resolution) = (self.origin, self.resolution);
+        let tick = self.tick_floor(now);
+        self.wheel.rewind(tick, |(_, expiration)| {
+            expiration.map(|expiration| tick_ceil(origin, resolution, expiration))
+        });
+        tick
+    }
+}
+
+fn tick_ceil(origin: Instant, resolution: Duration, instant: Instant) -> u64 {
+    let elapsed = instant.saturating_duration_since(origin).as_nanos();
+    u64::try_from(elapsed.div_ceil(resolution.as_nanos())).unwrap_or(u64::MAX)
+}
+
+fn duration_from_nanos(nanos: u128) -> Duration {
+    Duration::new(
+        u64::try_from(nanos / 1_000_000_000).unwrap_or(u64::MAX),
+        (nanos % 1_000_000_000) as u32,
+    )
+}
+
+impl<T> Backend<T> {
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Backend::Heap(heap) => heap.len(),
+            Backend::Wheel(wheel) => wheel.wheel.len(),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub(crate) fn push_with_priority(
+        &mut self,
+        t: T,
+        expiration: Option<Instant>,
+        priority: u32,
+    ) -> Key {
+        match self {
+            Backend::Heap(heap) => heap.push_with_priority(t, expiration, priority),
+            Backend::Wheel(wheel) => {
+                let tick = expiration.map(|expiration| wheel.tick_ceil(expiration));
+                wheel
+                    .wheel
+                    .push_with_priority((t, expiration), tick, priority)
+            }
+        }
+    }
+
+    /// Pushes every item with the default priority, in order.
+    pub(crate) fn extend(&mut self, items: Vec<(T, Option<Instant>)>) {
+        match self {
+            Backend::Heap(heap) => heap.extend(items),
+            Backend::Wheel(_) => {
+                for (t, expiration) in items {
+                    self.push_with_priority(t, expiration, 0);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn contains(&self, key: Key) -> bool {
+        match self {
+            Backend::Heap(heap) => heap.contains(key),
+            Backend::Wheel(wheel) => wheel.wheel.contains(key),
+        }
+    }
+
+    pub(crate) fn get(&self, key: Key) -> Option<&T> {
+        match self {
+            Backend::Heap(heap) => heap.get(key),
+            Backend::Wheel(wheel) => wheel.wheel.get(key).map(|(t, _)| t),
+        }
+    }
+
+    pub(crate) fn get_mut(&mut self, key: Key) -> Option<&mut T> {
+        match self {
+            Backend::Heap(heap) => heap.get_mut(key),
+            Backend::Wheel(wheel) => wheel.wheel.get_mut(key).map(|(t, _)| t),
+        }
+    }
+
+    /// See `TimedHeap::seq`.
+    pub(crate) fn seq(&self, key: Key) -> Option<u64> {
+        match self {
+            Backend::Heap(heap) => heap.seq(key),
+            Backend::Wheel(wheel) => wheel.wheel.seq(key),
+        }
+    }
+
+    pub(crate) fn remove(&mut self, key: Key) -> Option<T> {
+        match self {
+            Backend::Heap(heap) => heap.remove(key),
+            Backend::Wheel(wheel) => wheel.wheel.remove(key).map(|(t, _)| t),
+        }
+    }
+
+    pub(crate) fn reschedule(&mut self, key: Key, expiration: Option<Instant>) -> bool {
+        match self {
+            Backend::Heap(heap) => heap.reschedule(key, expiration),
+            Backend::Wheel(wheel) => {
+                let tick = expiration.map(|expiration| wheel.tick_ceil(expiration));
+                match wheel.wheel.get_mut(key) {
+                    Some(item) => item.1 = expiration,
+                    None => return false,
+                }
+                wheel.wheel.reschedule(key, tick)
+            }
+        }
+    }
+
+    /// Returns the expiration of the item that would be returned next, if any. With a wheel, this is when the
+    /// item will actually be returned, i.e. its expiration rounded up to the resolution.
+    pub(crate) fn peek(&mut self, now: Instant) -> Option<Option<Instant>> {
+        match self {
+            Backend::Heap(heap) => heap.peek(now),
+            Backend::Wheel(wheel) => {
+                let now = wheel.now_tick(now);
+                let next = wheel.wheel.peek(now)?;
+                Some(next.map(|tick| wheel.instant(tick)))
+            }
+        }
+    }
+
+    pub(crate) fn pop_due(&mut self, now: Instant) -> Option<(T, Option<Instant>)> {
+        match self {
+            Backend::Heap(heap) => heap.pop_due(now),
+            Backend::Wheel(wheel) => {
+                let now = wheel.now_tick(now);
+                wheel.wheel.pop_due(now).map(|(item, _)| item)
+            }
+        }
+    }
+
+    /// Returns the priority and expiration of the item `pop_due(now)` would return, if any.
+    pub(crate) fn peek_due(&mut self, now: Instant) -> Option<(u32, Option<Instant>)> {
+        match self {
+            Backend::Heap(heap) => heap.peek_due(now),
+            Backend::Wheel(wheel) => {
+                let now = wheel.now_tick(now);
+                let (priority, tick) = wheel.wheel.peek_due(now)?;
+                Some((priority, tick.map(|tick| wheel.instant(tick))))
+            }
+        }
+    }
+
+    /// Returns the key of the item `pop_due(now)` would return, if any.
+    pub(crate) fn due_key(&mut self, now: Instant) -> Option<Key> {
+        match self {
+            Backend::Heap(heap) => heap.due_key(now),
+            Backend::Wheel(wheel) => {
+                let now = wheel.now_tick(now);
+                wheel.wheel.due_key(now)
+            }
+        }
+    }
+
+    /// When to check for due items next, if there is nothing due at `now`. Cheaper than `peek`, but with a wheel
+    /// possibly earlier than the next expiration.
+    pub(crate) fn next_wakeup(&mut self, now: Instant) -> Option<Instant> {
+        match self {
+            Backend::Heap(heap) => heap.peek(now).flatten(),
+            Backend::Wheel(wheel) => wheel.wheel.next_wakeup().map(|tick| wheel.instant(tick)),
+        }
+    }
+
+    pub(crate) fn is_pending_head(&self, key: Key) -> bool {
+        match self {
+            Backend::Heap(heap) => heap.is_pending_head(key),
+            Backend::Wheel(wheel) => wheel.wheel.is_pending_head(key),
+        }
+    }
+}
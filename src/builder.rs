@@ -0,0 +1,179 @@
+use std::sync::Arc;
+use std::sync::Weak;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::backend::Backend;
+use crate::backend::Wheel;
+use crate::clock::DefaultClock;
+use crate::clock::Wake;
+use crate::queue::SharedInner;
+use crate::queue::State;
+use crate::timer::DefaultTimer;
+use crate::BuildError;
+use crate::Clock;
+use crate::ClockWaker;
+use crate::RetryPolicy;
+use crate::TimedHeap;
+use crate::TimedQueue;
+use crate::Timer;
+
+/// Configures a `TimedQueue`. Created by `TimedQueue::builder`.
+///
+/// Options that are not set default to those of `TimedQueue::new`. Options are checked against each other by
+/// `build`.
+pub struct TimedQueueBuilder<T> {
+    clock: Arc<dyn Clock>,
+    timer: Arc<dyn Timer>,
+    capacity: usize,
+    capacity_limit: Option<usize>,
+    timing_wheel: Option<Duration>,
+    resolution: Option<Duration>,
+    retry_policy: RetryPolicy,
+    items: Vec<(T, Option<Instant>)>,
+    /// Registers the built queue with `clock`, if that was set; only the default clock can do without.
+    register: Option<fn(&TimedQueue<T>)>,
+}
+
+impl<T> Default for TimedQueueBuilder<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> TimedQueueBuilder<T> {
+    pub fn new() -> Self {
+        Self {
+            clock: Arc::new(DefaultClock::default()),
+            timer: Arc::new(DefaultTimer::default()),
+            capacity: 0,
+            capacity_limit: None,
+            timing_wheel: None,
+            resolution: None,
+            retry_policy: RetryPolicy::default(),
+            items: Vec::new(),
+            register: None,
+        }
+    }
+
+    /// Compares expirations against `clock`. See `TimedQueue::with_clock`.
+    ///
+    /// The clock keeps a waker for the queue (see `Clock::register`), hence the `Send` bound on the items.
+    pub fn clock<C>(self, clock: C) -> Self
+    where
+        C: Clock,
+        T: Send + 'static,
+    {
+        Self {
+            clock: Arc::new(clock),
+            register: Some(register::<T>),
+            ..self
+        }
+    }
+
+    /// Has async consumers sleep on `timer`. See `TimedQueue::with_timer`.
+    pub fn timer<M>(self, timer: M) -> Self
+    where
+        M: Timer,
+    {
+        Self {
+            timer: Arc::new(timer),
+            ..self
+        }
+    }
+
+    /// Allocates room for `capacity` items up front, so that the queue does not reallocate until it holds more.
+    pub fn with_capacity(self, capacity: usize) -> Self {
+        Self { capacity, ..self }
+    }
+
+    /// Holds at most `limit` items at a time, which must be at least 1. See `TimedQueue::with_capacity_limit`.
+    pub fn capacity_limit(self, limit: usize) -> Self {
+        Self {
+            capacity_limit: Some(limit),
+            ..self
+        }
+    }
+
+    /// Keeps items in a timing wheel ticking every `tick`, which must be non-zero. See
+    /// `TimedQueue::with_timing_wheel`.
+    pub fn timing_wheel(self, tick: Duration) -> Self {
+        Self {
+            timing_wheel: Some(tick),
+            ..self
+        }
+    }
+
+    /// Lets consumers wake up once for all the items expiring within the same `window`, rather than once per
+    /// distinct expiration.
+    ///
+    /// Time is divided into consecutive windows of this length from the queue's creation, and consumers waiting
+    /// for an item sleep until the end of the window its expiration falls in. Items are still never returned
+    /// before their expiration, but may be returned up to `window` late. A zero `window` (the default) means
+    /// waking at each expiration exactly.
+    pub fn resolution(self, window: Duration) -> Self {
+        Self {
+            resolution: Some(window).filter(|window| *window > Duration::from_secs(0)),
+            ..self
+        }
+    }
+
+    /// Has `TimedQueue::retry` and `TimedQueue::enqueue_retry` wait according to `policy`, rather than
+    /// `RetryPolicy::default()`.
+    pub fn retry_policy(self, retry_policy: RetryPolicy) -> Self {
+        Self {
+            retry_policy,
+            ..self
+        }
+    }
+
+    /// Starts the queue out with `items`, as if each were passed to `enqueue` in turn. With the default
+    /// binary heap, they are loaded all at once in time linear in their number.
+    pub fn items<I>(mut self, items: I) -> Self
+    where
+        I: IntoIterator<Item = (T, Option<Instant>)>,
+    {
+        self.items.extend(items);
+        self
+    }
+
+    /// Creates the queue, or returns an error if the options are invalid or conflict with each other.
+    pub fn build(self) -> Result<TimedQueue<T>, BuildError> {
+        match self.capacity_limit {
+            Some(0) => return Err(BuildError::ZeroCapacityLimit),
+            Some(limit) if self.capacity > limit => return Err(BuildError::CapacityOverLimit),
+            Some(limit) if self.items.len() > limit => return Err(BuildError::TooManyItems),
+            _ => {}
+        }
+        let now = self.clock.now();
+        let mut items = match self.timing_wheel {
+            Some(tick) if tick == Duration::from_secs(0) => return Err(BuildError::ZeroTick),
+            Some(tick) => Backend::Wheel(Wheel::with_capacity(now, tick, self.capacity)),
+            None => Backend::Heap(TimedHeap::with_capacity(self.capacity)),
+        };
+        items.extend(self.items);
+        let state = State::new(items, self.resolution.map(|window| (now, window)));
+        let queue = TimedQueue {
+            inner: Arc::new(SharedInner::new(
+                state,
+                self.capacity_limit,
+                self.clock,
+                self.timer,
+                self.retry_policy,
+            )),
+        };
+        if let Some(register) = self.register {
+            register(&queue);
+        }
+        Ok(queue)
+    }
+}
+
+/// Registers `queue` with its clock, so that the clock can wake its consumers.
+pub(crate) fn register<T>(queue: &TimedQueue<T>)
+where
+    T: Send + 'static,
+{
+    let waker: Weak<dyn Wake> = Arc::downgrade(&queue.inner) as Weak<SharedInner<T>>;
+    queue.inner.clock.register(ClockWaker(waker));
+}
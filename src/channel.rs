@@ -0,0 +1,213 @@
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+
+use crate::CloseMode;
+use crate::EnqueueError;
+use crate::Handle;
+use crate::TimedQueue;
+use crate::TimedQueueStream;
+use crate::TryEnqueueError;
+
+/// Creates a new queue, returning separate producer and consumer halves.
+///
+/// Both halves can be cloned. Once every `TimedSender` has been dropped the queue is closed with
+/// `CloseMode::Drain`, so receivers get the remaining items as they become due and then `None`.
+pub fn channel<T>() -> (TimedSender<T>, TimedReceiver<T>) {
+    let queue = TimedQueue::new();
+    queue.inner.senders.fetch_add(1, Ordering::Relaxed);
+    (
+        TimedSender {
+            queue: queue.clone(),
+        },
+        TimedReceiver { queue },
+    )
+}
+
+/// The producing half of a queue created by `channel`.
+pub struct TimedSender<T> {
+    queue: TimedQueue<T>,
+}
+
+/// The consuming half of a queue created by `channel`.
+pub struct TimedReceiver<T> {
+    queue: TimedQueue<T>,
+}
+
+impl<T> Clone for TimedSender<T> {
+    fn clone(&self) -> Self {
+        self.queue.inner.senders.fetch_add(1, Ordering::Relaxed);
+        Self {
+            queue: self.queue.clone(),
+        }
+    }
+}
+
+impl<T> Drop for TimedSender<T> {
+    fn drop(&mut self) {
+        if self.queue.inner.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.queue.close(CloseMode::Drain);
+        }
+    }
+}
+
+impl<T> TimedSender<T> {
+    /// See `TimedQueue::enqueue`.
+    pub fn enqueue(
+        &self,
+        t: T,
+        expiration: Option<Instant>,
+    ) -> Result<Handle<T>, TryEnqueueError<T>> {
+        self.queue.enqueue(t, expiration)
+    }
+
+    /// See `TimedQueue::enqueue_after`.
+    pub fn enqueue_after(&self, t: T, delay: Duration) -> Result<Handle<T>, TryEnqueueError<T>> {
+        self.queue.enqueue_after(t, delay)
+    }
+
+    /// See `TimedQueue::enqueue_now`.
+    pub fn enqueue_now(&self, t: T) -> Result<Handle<T>, TryEnqueueError<T>> {
+        self.queue.enqueue_now(t)
+    }
+
+    /// See `TimedQueue::enqueue_at_system_time`.
+    pub fn enqueue_at_system_time(
+        &self,
+        t: T,
+        expiration: SystemTime,
+    ) -> Result<Handle<T>, TryEnqueueError<T>> {
+        self.queue.enqueue_at_system_time(t, expiration)
+    }
+
+    /// See `TimedQueue::enqueue_with_priority`.
+    pub fn enqueue_with_priority(
+        &self,
+        t: T,
+        expiration: Option<Instant>,
+        priority: u32,
+    ) -> Result<Handle<T>, TryEnqueueError<T>> {
+        self.queue.enqueue_with_priority(t, expiration, priority)
+    }
+
+    /// See `TimedQueue::try_enqueue`.
+    pub fn try_enqueue(
+        &self,
+        t: T,
+        expiration: Option<Instant>,
+    ) -> Result<Handle<T>, TryEnqueueError<T>> {
+        self.queue.try_enqueue(t, expiration)
+    }
+
+    /// See `TimedQueue::enqueue_wait`.
+    pub async fn enqueue_wait(
+        &self,
+        t: T,
+        expiration: Option<Instant>,
+    ) -> Result<Handle<T>, EnqueueError<T>> {
+        self.queue.enqueue_wait(t, expiration).await
+    }
+
+    /// See `TimedQueue::enqueue_blocking`.
+    pub fn enqueue_blocking(
+        &self,
+        t: T,
+        expiration: Option<Instant>,
+    ) -> Result<Handle<T>, EnqueueError<T>> {
+        self.queue.enqueue_blocking(t, expiration)
+    }
+
+    /// See `TimedQueue::reschedule`.
+    pub fn reschedule(&self, handle: &Handle<T>, expiration: Option<Instant>) -> bool {
+        self.queue.reschedule(handle, expiration)
+    }
+
+    /// Closes the queue before the last sender is dropped. See `TimedQueue::close`.
+    pub fn close(&self, mode: CloseMode) {
+        self.queue.close(mode)
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.queue.is_closed()
+    }
+}
+
+impl<T> Clone for TimedReceiver<T> {
+    fn clone(&self) -> Self {
+        Self {
+            queue: self.queue.clone(),
+        }
+    }
+}
+
+impl<T> TimedReceiver<T> {
+    /// See `TimedQueue::dequeue`.
+    pub async fn dequeue(&self) -> Option<(T, Option<Instant>)> {
+        self.queue.dequeue().await
+    }
+
+    /// See `TimedQueue::dequeue_timeout`.
+    pub async fn dequeue_timeout(&self, duration: Duration) -> Option<(T, Option<Instant>)> {
+        self.queue.dequeue_timeout(duration).await
+    }
+
+    /// See `TimedQueue::dequeue_until`.
+    pub async fn dequeue_until(&self, deadline: Instant) -> Option<(T, Option<Instant>)> {
+        self.queue.dequeue_until(deadline).await
+    }
+
+    /// See `TimedQueue::dequeue_batch`.
+    pub async fn dequeue_batch(&self, max: usize) -> Vec<(T, Option<Instant>)> {
+        self.queue.dequeue_batch(max).await
+    }
+
+    /// See `TimedQueue::dequeue_blocking`.
+    pub fn dequeue_blocking(&self) -> Option<(T, Option<Instant>)> {
+        self.queue.dequeue_blocking()
+    }
+
+    /// See `TimedQueue::dequeue_blocking_timeout`.
+    pub fn dequeue_blocking_timeout(&self, timeout: Duration) -> Option<(T, Option<Instant>)> {
+        self.queue.dequeue_blocking_timeout(timeout)
+    }
+
+    /// See `TimedQueue::try_dequeue`.
+    pub fn try_dequeue(&self) -> Option<(T, Option<Instant>)> {
+        self.queue.try_dequeue()
+    }
+
+    /// See `TimedQueue::drain_due`.
+    pub fn drain_due(&self) -> Vec<(T, Option<Instant>)> {
+        self.queue.drain_due()
+    }
+
+    /// See `TimedQueue::peek_deadline`.
+    pub fn peek_deadline(&self) -> Option<Option<Instant>> {
+        self.queue.peek_deadline()
+    }
+
+    /// See `TimedQueue::next_ready_in`.
+    pub fn next_ready_in(&self) -> Option<Duration> {
+        self.queue.next_ready_in()
+    }
+
+    /// See `TimedQueue::stream`.
+    pub fn stream(&self) -> TimedQueueStream<T> {
+        self.queue.stream()
+    }
+
+    /// See `TimedQueue::into_stream`.
+    pub fn into_stream(self) -> TimedQueueStream<T> {
+        self.queue.into_stream()
+    }
+
+    /// Closes the queue from the consuming side. See `TimedQueue::close`.
+    pub fn close(&self, mode: CloseMode) {
+        self.queue.close(mode)
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.queue.is_closed()
+    }
+}
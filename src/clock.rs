@@ -0,0 +1,261 @@
+use std::convert::TryFrom;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::Weak;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+/// The source of "now" for a `TimedQueue`, against which expirations are compared.
+///
+/// Expirations are still expressed as `Instant`s, but only ever compared with this clock's `now`, so a clock
+/// need not agree with `Instant::now()`.
+pub trait Clock: Send + Sync + 'static {
+    fn now(&self) -> Instant;
+
+    /// How long to really sleep while waiting for an expiration that is `remaining` away according to `now`,
+    /// or `None` to wait until the queue is woken through a `ClockWaker`.
+    fn sleep_duration(&self, remaining: Duration) -> Option<Duration> {
+        Some(remaining)
+    }
+
+    /// Called with a waker for each queue using this clock. Clocks whose time can move other than by the
+    /// passage of real time should keep it and call `ClockWaker::wake` when it does.
+    fn register(&self, _waker: ClockWaker) {}
+
+    /// Converts a wall-clock time to this clock's timeline.
+    ///
+    /// By default this goes through the current offset between `now` and `SystemTime::now()`, so the result
+    /// does not follow later changes to the system time; see `WallClock` for that.
+    fn instant_from_system_time(&self, time: SystemTime) -> Instant {
+        let now = self.now();
+        match time.duration_since(SystemTime::now()) {
+            Ok(ahead) => add_saturating(now, ahead),
+            Err(behind) => now.checked_sub(behind.duration()).unwrap_or(now),
+        }
+    }
+
+    /// The inverse of `instant_from_system_time`.
+    fn system_time_from_instant(&self, instant: Instant) -> SystemTime {
+        let now = self.now();
+        match instant.checked_duration_since(now) {
+            Some(ahead) => SystemTime::now() + ahead,
+            None => SystemTime::now()
+                .checked_sub(now.duration_since(instant))
+                .unwrap_or(UNIX_EPOCH),
+        }
+    }
+}
+
+/// Instants this far ahead are treated as "never"; far enough for any practical timer, close enough not to
+/// overflow `Instant` on any platform.
+const FAR_FUTURE: Duration = Duration::from_secs(86400 * 365 * 30);
+
+/// `instant + duration`, or a far-future instant if that overflows.
+pub(crate) fn add_saturating(instant: Instant, duration: Duration) -> Instant {
+    instant
+        .checked_add(duration)
+        .unwrap_or_else(|| instant + FAR_FUTURE)
+}
+
+/// The first instant at or after `instant` that is a whole number of `resolution`s after `origin`.
+pub(crate) fn round_up(instant: Instant, origin: Instant, resolution: Duration) -> Instant {
+    let resolution = resolution.as_nanos();
+    let since_origin = instant.saturating_duration_since(origin).as_nanos();
+    let nanos = since_origin.div_ceil(resolution) * resolution;
+    let since_origin = Duration::new(
+        u64::try_from(nanos / 1_000_000_000).unwrap_or(u64::MAX),
+        (nanos % 1_000_000_000) as u32,
+    );
+    add_saturating(origin, since_origin)
+}
+
+/// `Instant::now()`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// The clock used by queues that are not given one: `TokioClock` with the `tokio` feature, else `SystemClock`.
+#[cfg(feature = "tokio")]
+pub(crate) type DefaultClock = TokioClock;
+#[cfg(not(feature = "tokio"))]
+pub(crate) type DefaultClock = SystemClock;
+
+/// The default clock with the `tokio` feature: `tokio::time::Instant::now()`.
+///
+/// This is the system clock, except that it follows `tokio::time::pause` and `advance` (with tokio's
+/// `test-util` feature), like the timers consumers sleep on. Under paused time, compute expirations from
+/// `tokio::time::Instant::now().into_std()` rather than `Instant::now()`.
+#[cfg(feature = "tokio")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TokioClock;
+
+#[cfg(feature = "tokio")]
+impl Clock for TokioClock {
+    fn now(&self) -> Instant {
+        tokio::time::Instant::now().into_std()
+    }
+}
+
+/// A clock that follows the system's wall-clock time, for expirations that are persisted as `SystemTime`s
+/// and must be honoured across restarts and hosts.
+///
+/// Its `Instant`s are a linear image of `SystemTime` (fixed when the clock is created), so when the system
+/// time is stepped forwards or backwards, expirations move with it. Since a sleeping consumer cannot notice
+/// such a step, sleeps are capped (at one second by default) and the head of the queue is re-evaluated after
+/// each one.
+#[derive(Clone, Copy, Debug)]
+pub struct WallClock {
+    anchor: Instant,
+    anchor_system_time: SystemTime,
+    max_sleep: Duration,
+}
+
+impl Default for WallClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WallClock {
+    pub fn new() -> Self {
+        Self {
+            anchor: Instant::now(),
+            anchor_system_time: SystemTime::now(),
+            max_sleep: Duration::from_secs(1),
+        }
+    }
+
+    /// Sets the longest a consumer sleeps before checking whether the system time has been changed.
+    pub fn with_max_sleep(self, max_sleep: Duration) -> Self {
+        Self { max_sleep, ..self }
+    }
+}
+
+impl Clock for WallClock {
+    fn now(&self) -> Instant {
+        self.instant_from_system_time(SystemTime::now())
+    }
+
+    fn sleep_duration(&self, remaining: Duration) -> Option<Duration> {
+        Some(remaining.min(self.max_sleep))
+    }
+
+    fn instant_from_system_time(&self, time: SystemTime) -> Instant {
+        match time.duration_since(self.anchor_system_time) {
+            Ok(after) => add_saturating(self.anchor, after),
+            // Before the anchor, and possibly before anything `Instant` can represent; such times are long
+            // overdue either way.
+            Err(before) => self
+                .anchor
+                .checked_sub(before.duration())
+                .unwrap_or(self.anchor),
+        }
+    }
+
+    fn system_time_from_instant(&self, instant: Instant) -> SystemTime {
+        match instant.checked_duration_since(self.anchor) {
+            Some(after) => self.anchor_system_time + after,
+            None => self
+                .anchor_system_time
+                .checked_sub(self.anchor.duration_since(instant))
+                .unwrap_or(UNIX_EPOCH),
+        }
+    }
+}
+
+pub(crate) trait Wake: Send + Sync {
+    fn wake(&self);
+}
+
+/// Wakes every consumer of a queue so that it re-checks the time. See `Clock::register`.
+#[derive(Clone)]
+pub struct ClockWaker(pub(crate) Weak<dyn Wake>);
+
+impl ClockWaker {
+    /// Returns `false` if the queue no longer exists, in which case the waker can be dropped.
+    pub fn wake(&self) -> bool {
+        match self.0.upgrade() {
+            Some(queue) => {
+                queue.wake();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// A clock that only moves when told to, for deterministic tests.
+///
+/// Consumers of a queue using this clock never sleep on a timer; they wait until the clock is advanced.
+/// Clones share the same time.
+#[derive(Clone)]
+pub struct ManualClock {
+    inner: Arc<ManualClockInner>,
+}
+
+struct ManualClockInner {
+    now: Mutex<Instant>,
+    wakers: Mutex<Vec<ClockWaker>>,
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ManualClock {
+    /// Creates a clock that starts at the current real time.
+    pub fn new() -> Self {
+        Self::starting_at(Instant::now())
+    }
+
+    pub fn starting_at(now: Instant) -> Self {
+        Self {
+            inner: Arc::new(ManualClockInner {
+                now: Mutex::new(now),
+                wakers: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.inner.now.lock().unwrap() += duration;
+        self.wake();
+    }
+
+    /// Moves the clock to `now`, which may be earlier than its current time.
+    pub fn set(&self, now: Instant) {
+        *self.inner.now.lock().unwrap() = now;
+        self.wake();
+    }
+
+    fn wake(&self) {
+        self.inner
+            .wakers
+            .lock()
+            .unwrap()
+            .retain(|waker| waker.wake());
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.inner.now.lock().unwrap()
+    }
+
+    fn sleep_duration(&self, _remaining: Duration) -> Option<Duration> {
+        None
+    }
+
+    fn register(&self, waker: ClockWaker) {
+        self.inner.wakers.lock().unwrap().push(waker);
+    }
+}
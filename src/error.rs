@@ -0,0 +1,88 @@
+use std::error::Error;
+use std::fmt;
+
+/// Error returned by `TimedQueue::enqueue_wait` and `enqueue_blocking`, which only fail once the queue is closed.
+/// The rejected item is handed back.
+pub struct EnqueueError<T>(pub T);
+
+impl<T> EnqueueError<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> fmt::Debug for EnqueueError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EnqueueError(..)")
+    }
+}
+
+impl<T> fmt::Display for EnqueueError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("queue is closed")
+    }
+}
+
+impl<T> Error for EnqueueError<T> {}
+
+/// Error returned by `TimedQueue::enqueue` and its variants. The rejected item is handed back.
+pub enum TryEnqueueError<T> {
+    /// The queue is at its capacity limit.
+    Full(T),
+    /// The queue has been closed.
+    Closed(T),
+}
+
+impl<T> TryEnqueueError<T> {
+    pub fn into_inner(self) -> T {
+        match self {
+            TryEnqueueError::Full(t) | TryEnqueueError::Closed(t) => t,
+        }
+    }
+}
+
+impl<T> fmt::Debug for TryEnqueueError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryEnqueueError::Full(_) => f.write_str("Full(..)"),
+            TryEnqueueError::Closed(_) => f.write_str("Closed(..)"),
+        }
+    }
+}
+
+impl<T> fmt::Display for TryEnqueueError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryEnqueueError::Full(_) => f.write_str("queue is full"),
+            TryEnqueueError::Closed(_) => f.write_str("queue is closed"),
+        }
+    }
+}
+
+impl<T> Error for TryEnqueueError<T> {}
+
+/// Error returned by `TimedQueueBuilder::build` when the options set are invalid or conflict with each other.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BuildError {
+    /// `capacity_limit` was zero.
+    ZeroCapacityLimit,
+    /// `timing_wheel` was given a zero tick.
+    ZeroTick,
+    /// `with_capacity` asked for room for more items than the `capacity_limit` allows.
+    CapacityOverLimit,
+    /// More initial `items` were given than the `capacity_limit` allows.
+    TooManyItems,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BuildError::ZeroCapacityLimit => "capacity limit must be at least 1",
+            BuildError::ZeroTick => "timing wheel resolution must be non-zero",
+            BuildError::CapacityOverLimit => "initial capacity exceeds the capacity limit",
+            BuildError::TooManyItems => "more initial items than the capacity limit",
+        })
+    }
+}
+
+impl Error for BuildError {}
@@ -0,0 +1,331 @@
+use alloc::collections::BinaryHeap;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+use core::iter::FromIterator;
+
+use crate::slab::Slab;
+
+/// Identifies an item in a `TimedHeap` or `TimingWheel`. Keys are never reused by the structure that issued them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Key {
+    pub(crate) index: usize,
+    pub(crate) id: u64,
+}
+
+/// Heap entries only carry the ordering key; the items themselves live in `TimedHeap::entries`,
+/// so that they can be removed without searching the heap.
+///
+/// Every time an item is scheduled it gets a fresh `seq`, which also breaks ties between equal expirations
+/// in FIFO order.
+/// A heap entry whose `seq` no longer matches its item's is stale (the item was removed or rescheduled).
+#[derive(Eq, PartialEq, Ord, PartialOrd)]
+struct Item<Tick> {
+    expiration: Reverse<Option<Tick>>,
+    seq: Reverse<u64>,
+    key: Key,
+}
+
+/// An item that has become due, ordered by priority first.
+#[derive(Eq, PartialEq, Ord, PartialOrd)]
+pub(crate) struct ReadyItem<Tick> {
+    pub(crate) priority: u32,
+    pub(crate) expiration: Reverse<Option<Tick>>,
+    pub(crate) seq: Reverse<u64>,
+    pub(crate) key: Key,
+}
+
+pub(crate) struct Entry<T, Tick> {
+    pub(crate) inner: T,
+    pub(crate) expiration: Option<Tick>,
+    pub(crate) priority: u32,
+    pub(crate) seq: u64,
+}
+
+impl<T, Tick> Entry<T, Tick> {
+    pub(crate) fn new(inner: T, expiration: Option<Tick>, priority: u32, seq: u64) -> Self {
+        Self {
+            inner,
+            expiration,
+            priority,
+            seq,
+        }
+    }
+}
+
+/// The ordering at the core of `TimedQueue`, without any synchronization or notion of real time, so that it
+/// can be used on `no_std` targets with their own tick source.
+///
+/// Each item has an expiration `Tick` (any totally ordered time, such as `Instant` or a hardware tick count),
+/// or `None` meaning "as soon as possible". Items are popped once due at the `now` passed in, in order of
+/// expiration; items with equal expirations in the order they were pushed (or last rescheduled). Among items
+/// that are already due, those with a higher priority come first.
+pub struct TimedHeap<T, Tick> {
+    /// Items that are not yet known to be due.
+    heap: BinaryHeap<Item<Tick>>,
+    /// Items that are due, waiting to be returned in priority order.
+    ready: BinaryHeap<ReadyItem<Tick>>,
+    entries: Slab<Entry<T, Tick>>,
+    next_seq: u64,
+}
+
+impl<T, Tick> Default for TimedHeap<T, Tick>
+where
+    Tick: Ord + Copy,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Pushes every item, in order. Faster than pushing them one at a time when there are many: the heap is rebuilt
+/// once, in time linear in its new size, rather than sifting each item into place.
+impl<T, Tick> Extend<(T, Option<Tick>)> for TimedHeap<T, Tick>
+where
+    Tick: Ord + Copy,
+{
+    fn extend<I>(&mut self, items: I)
+    where
+        I: IntoIterator<Item = (T, Option<Tick>)>,
+    {
+        let items = items.into_iter();
+        self.entries.reserve(items.size_hint().0);
+        let scheduled: Vec<_> = items
+            .map(|(t, expiration)| {
+                let seq = self.next_seq;
+                self.next_seq += 1;
+                let key = self.entries.insert(seq, Entry::new(t, expiration, 0, seq));
+                Item {
+                    expiration: Reverse(expiration),
+                    seq: Reverse(seq),
+                    key,
+                }
+            })
+            .collect();
+        // `BinaryHeap::extend` rebuilds the whole heap when that is cheaper than sifting up each new item.
+        self.heap.extend(scheduled);
+    }
+}
+
+impl<T, Tick> FromIterator<(T, Option<Tick>)> for TimedHeap<T, Tick>
+where
+    Tick: Ord + Copy,
+{
+    fn from_iter<I>(items: I) -> Self
+    where
+        I: IntoIterator<Item = (T, Option<Tick>)>,
+    {
+        let mut heap = Self::new();
+        heap.extend(items);
+        heap
+    }
+}
+
+impl<T, Tick> TimedHeap<T, Tick>
+where
+    Tick: Ord + Copy,
+{
+    pub fn new() -> Self {
+        Self {
+            heap: BinaryHeap::new(),
+            ready: BinaryHeap::new(),
+            entries: Slab::new(),
+            next_seq: 0,
+        }
+    }
+
+    /// Creates an empty heap with room for `capacity` items before reallocating.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            heap: BinaryHeap::with_capacity(capacity),
+            ready: BinaryHeap::new(),
+            entries: Slab::with_capacity(capacity),
+            next_seq: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.len() == 0
+    }
+
+    /// Adds `t`, to be popped no earlier than `expiration` (or as soon as possible if `None`).
+    pub fn push(&mut self, t: T, expiration: Option<Tick>) -> Key {
+        self.push_with_priority(t, expiration, 0)
+    }
+
+    /// Like `push`, but among items that are due, those with a higher `priority` are popped first.
+    pub fn push_with_priority(&mut self, t: T, expiration: Option<Tick>, priority: u32) -> Key {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let key = self
+            .entries
+            .insert(seq, Entry::new(t, expiration, priority, seq));
+        self.heap.push(Item {
+            expiration: Reverse(expiration),
+            seq: Reverse(seq),
+            key,
+        });
+        key
+    }
+
+    /// Returns whether the item is still in the heap.
+    pub fn contains(&self, key: Key) -> bool {
+        self.entries.get(key).is_some()
+    }
+
+    /// Returns the item, if it is still in the heap.
+    pub fn get(&self, key: Key) -> Option<&T> {
+        self.entries.get(key).map(|entry| &entry.inner)
+    }
+
+    /// Returns the item, if it is still in the heap.
+    pub fn get_mut(&mut self, key: Key) -> Option<&mut T> {
+        self.entries.get_mut(key).map(|entry| &mut entry.inner)
+    }
+
+    /// Removes the item, returning it if it had not been popped or removed already.
+    pub fn remove(&mut self, key: Key) -> Option<T> {
+        let entry = self.entries.remove(key)?;
+        self.compact();
+        Some(entry.inner)
+    }
+
+    /// Moves the item to a new expiration, earlier or later. Returns `false` if it is no longer in the heap.
+    pub fn reschedule(&mut self, key: Key, expiration: Option<Tick>) -> bool {
+        if self.entries.get(key).is_none() {
+            return false;
+        }
+        let seq = self.schedule(key, expiration);
+        let entry = self.entries.get_mut(key).unwrap();
+        entry.expiration = expiration;
+        entry.seq = seq;
+        self.compact();
+        true
+    }
+
+    /// Returns the expiration of the item that would be popped next, if any, discarding removed items on the way.
+    pub fn peek(&mut self, now: Tick) -> Option<Option<Tick>> {
+        self.promote(now);
+        while let Some(item) = self.ready.peek() {
+            if self.is_live(item.key, item.seq.0) {
+                return Some(item.expiration.0);
+            }
+            self.ready.pop();
+        }
+        self.pending_head()
+    }
+
+    /// Removes and returns the next item if it is due at `now`, along with its expiration.
+    pub fn pop_due(&mut self, now: Tick) -> Option<(T, Option<Tick>)> {
+        self.promote(now);
+        while let Some(ReadyItem { seq, key, .. }) = self.ready.pop() {
+            if self.is_live(key, seq.0) {
+                let Entry {
+                    inner, expiration, ..
+                } = self.entries.remove(key).unwrap();
+                return Some((inner, expiration));
+            }
+        }
+        None
+    }
+
+    /// Returns the priority and expiration of the item `pop_due(now)` would return, if any.
+    #[cfg(feature = "std")]
+    pub(crate) fn peek_due(&mut self, now: Tick) -> Option<(u32, Option<Tick>)> {
+        let key = self.due_key(now)?;
+        let entry = self.entries.get(key).unwrap();
+        Some((entry.priority, entry.expiration))
+    }
+
+    /// Returns the key of the item `pop_due(now)` would return, if any.
+    #[cfg(feature = "std")]
+    pub(crate) fn due_key(&mut self, now: Tick) -> Option<Key> {
+        self.promote(now);
+        while let Some(item) = self.ready.peek() {
+            if self.is_live(item.key, item.seq.0) {
+                return Some(item.key);
+            }
+            self.ready.pop();
+        }
+        None
+    }
+
+    /// Identifies the item's current schedule: it changes whenever the item is rescheduled.
+    #[cfg(feature = "std")]
+    pub(crate) fn seq(&self, key: Key) -> Option<u64> {
+        self.entries.get(key).map(|entry| entry.seq)
+    }
+
+    /// Whether `key` is at the top of the items that are not yet due, i.e. whoever is waiting for the next
+    /// expiration should wait for this one now.
+    #[cfg(feature = "std")]
+    pub(crate) fn is_pending_head(&self, key: Key) -> bool {
+        matches!(self.heap.peek(), Some(item) if item.key == key && self.is_live(key, item.seq.0))
+    }
+
+    fn is_live(&self, key: Key, seq: u64) -> bool {
+        matches!(self.entries.get(key), Some(entry) if entry.seq == seq)
+    }
+
+    /// Pushes a heap entry for `key` with a fresh sequence number, returning it.
+    fn schedule(&mut self, key: Key, expiration: Option<Tick>) -> u64 {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.heap.push(Item {
+            expiration: Reverse(expiration),
+            seq: Reverse(seq),
+            key,
+        });
+        seq
+    }
+
+    /// Moves every item that is due at `now` from `heap` to `ready`, dropping stale entries on the way.
+    fn promote(&mut self, now: Tick) {
+        while let Some(Item {
+            expiration: Reverse(expiration),
+            ..
+        }) = self.heap.peek()
+        {
+            if matches!(expiration, Some(expiration) if *expiration > now) {
+                break;
+            }
+            let Item {
+                expiration,
+                seq,
+                key,
+            } = self.heap.pop().unwrap();
+            if let Some(entry) = self.entries.get(key).filter(|entry| entry.seq == seq.0) {
+                self.ready.push(ReadyItem {
+                    priority: entry.priority,
+                    expiration,
+                    seq,
+                    key,
+                });
+            }
+        }
+    }
+
+    /// Discards stale entries from the top of `heap`, and returns the expiration of the next live item in it.
+    fn pending_head(&mut self) -> Option<Option<Tick>> {
+        while let Some(item) = self.heap.peek() {
+            if self.is_live(item.key, item.seq.0) {
+                return Some(item.expiration.0);
+            }
+            self.heap.pop();
+        }
+        None
+    }
+
+    /// Drops stale heap entries, once they make up most of the heaps.
+    fn compact(&mut self) {
+        if self.heap.len() + self.ready.len() > 2 * self.entries.len() + 32 {
+            let entries = &self.entries;
+            let is_live = |key, seq| matches!(entries.get(key), Some(entry) if entry.seq == seq);
+            self.heap.retain(|item| is_live(item.key, item.seq.0));
+            self.ready.retain(|item| is_live(item.key, item.seq.0));
+        }
+    }
+}
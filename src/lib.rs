@@ -2,131 +2,146 @@
 //!
 //! # Example
 //! Imagine the "new messages" queue of an SMTP server implementation. Delivery should be attempted immediately for new messages.
-//! Messages for which delivery fails should be retried after 30 minutes.
+//! Messages for which delivery fails should be retried with exponential backoff, starting at one minute and
+//! waiting at most a day, and given up on after ten retries. A message stays in the queue until it has been
+//! delivered, so that it is retried even if the delivery attempt is interrupted.
 //!
-//!```
-//! fn server_loop<I: IntoIterator<Item = MailMessage>>(tq: TimedQueue<MailMessage>, messages: I) {
+//!```no_run
+//! # use std::time::Duration;
+//! # use timed_queue::{Attempt, RetryPolicy, TimedQueue};
+//! # #[derive(Clone)]
+//! # struct MailMessage;
+//! # async fn try_deliver(_: &MailMessage) -> Result<(), ()> { Ok(()) }
+//! # fn get_message_stream() -> Vec<MailMessage> { vec![] }
+//! fn server_loop<I>(tq: TimedQueue<Attempt<MailMessage>>, messages: I)
+//! where
+//!     I: IntoIterator<Item = MailMessage>,
+//! {
 //!     for m in messages {
-//!         tq.enqueue(m, None);
+//!         tq.enqueue_retry(m, 0).unwrap();
 //!     }
 //! }
 //!
-//! async fn delivery_loop(tq: TimedQueue<MailMessage>) {
-//!     loop {
-//!         let (msg, _) = tq.dequeue().await;
-//!         if try_deliver(msg).await.is_err() {
-//!             tq.enqueue(msg, Some(Instant::now() + Duration::from_secs(30 * 60)));
+//! async fn delivery_loop(tq: TimedQueue<Attempt<MailMessage>>) {
+//!     while let Some((msg, lease)) = tq.lease(Duration::from_secs(5 * 60)).await {
+//!         if try_deliver(&msg.item).await.is_ok() || msg.attempt >= 10 {
+//!             lease.ack();
+//!         } else {
+//!             lease.retry();
 //!         }
 //!     }
 //! }
 //!
 //! #[tokio::main]
 //! async fn main() {
-//!     let tq = TimedQueue::new();
+//!     let tq = TimedQueue::builder()
+//!         .retry_policy(RetryPolicy::Exponential {
+//!             initial: Duration::from_secs(60),
+//!             max: Duration::from_secs(24 * 60 * 60),
+//!         })
+//!         .build()
+//!         .unwrap();
 //!     let tq2 = tq.clone();
 //!     std::thread::spawn(move || server_loop(tq, get_message_stream()));
 //!     tokio::spawn(delivery_loop(tq2));
 //! }
 //! ```
-use std::cmp::Reverse;
-use std::collections::BinaryHeap;
-use std::sync::Arc;
-use std::sync::Mutex;
-use std::time::Duration;
-use std::time::Instant;
-
-use tokio::sync::Notify;
-use tokio::time::timeout;
-
-#[derive(Eq, PartialEq, Ord, PartialOrd)]
-struct Item<T>
-where
-    T: Ord,
-{
-    expiration: Reverse<Option<Instant>>,
-    inner: T,
-}
+//!
+//! # Runtimes
+//! Only sleeping until the next expiration depends on an async runtime, through the `Timer` trait. With the
+//! `tokio` feature (enabled by default) queues use `TokioTimer`; the `async-std` and `smol` features provide
+//! `AsyncStdTimer` and `SmolTimer`, which become the default when `tokio` is disabled. With none of them,
+//! `ThreadTimer` is used, which works with any executor. `TimedQueue::with_timer` selects a timer explicitly.
+//!
+//! # `no_std`
+//! Without the `std` feature (enabled by default), only `TimedHeap` is available: the ordering behind
+//! `TimedQueue`, without locking or waiting, over any tick type. It only needs `alloc`.
+#![cfg_attr(not(feature = "std"), no_std)]
 
-struct SharedInner<T>
-where
-    T: Ord,
-{
-    storage: Mutex<BinaryHeap<Item<T>>>,
-    notify: Notify,
-}
+extern crate alloc;
 
-#[derive(Clone)]
-pub struct TimedQueue<T>
-where
-    T: Ord,
-{
-    inner: Arc<SharedInner<T>>,
-}
+mod heap;
+mod slab;
+mod wheel;
 
-impl<T> TimedQueue<T>
-where
-    T: Ord,
-{
-    pub fn new() -> Self {
-        Self {
-            inner: Arc::new(SharedInner {
-                storage: Mutex::new(BinaryHeap::new()),
-                notify: Notify::new(),
-            }),
-        }
-    }
-    pub fn enqueue(&self, t: T, expiration: Option<Instant>) {
-        self.inner.storage.lock().unwrap().push(Item {
-            expiration: Reverse(expiration),
-            inner: t,
-        });
-        self.inner.notify.notify_one();
-    }
+#[cfg(feature = "std")]
+mod backend;
+#[cfg(feature = "std")]
+mod builder;
+#[cfg(feature = "std")]
+mod channel;
+#[cfg(feature = "std")]
+mod clock;
+#[cfg(feature = "std")]
+mod error;
+#[cfg(feature = "std")]
+mod queue;
+#[cfg(feature = "std")]
+mod retry;
+#[cfg(feature = "std")]
+mod sharded;
+#[cfg(feature = "std")]
+mod stream;
+#[cfg(feature = "std")]
+mod timer;
 
-    fn peek_inner(&self) -> Result<(T, Option<Instant>), Option<Duration>> {
-        let now = Instant::now();
-        let mut lock = self.inner.storage.lock().unwrap();
-        let (ready, duration) = match lock.peek() {
-            Some(Item {
-                expiration: Reverse(Some(expiration)),
-                ..
-            }) => {
-                if *expiration < now {
-                    (true, None)
-                } else {
-                    (false, Some(*expiration - now))
-                }
-            }
-            Some(Item {
-                expiration: Reverse(None),
-                ..
-            }) => (true, None),
-            None => (false, None),
-        };
-        if ready {
-            let Item {
-                expiration: Reverse(expiration),
-                inner: item,
-            } = lock.pop().unwrap();
-            Ok((item, expiration))
-        } else {
-            Err(duration)
-        }
-    }
+pub use heap::Key;
+pub use heap::TimedHeap;
+pub use wheel::TimingWheel;
 
-    pub async fn dequeue(&self) -> (T, Option<Instant>) {
-        loop {
-            match self.peek_inner() {
-                Ok((item, duration)) => {
-                    break (item, duration);
-                }
-                Err(Some(duration)) => {
-                    let _ = timeout(duration, self.inner.notify.notified()).await;
-                }
-                Err(None) => {
-                    self.inner.notify.notified().await;
-                }
-            }
-        }
-    }
-}
+#[cfg(feature = "std")]
+pub use builder::TimedQueueBuilder;
+#[cfg(feature = "std")]
+pub use channel::channel;
+#[cfg(feature = "std")]
+pub use channel::TimedReceiver;
+#[cfg(feature = "std")]
+pub use channel::TimedSender;
+#[cfg(feature = "std")]
+pub use clock::Clock;
+#[cfg(feature = "std")]
+pub use clock::ClockWaker;
+#[cfg(feature = "std")]
+pub use clock::ManualClock;
+#[cfg(feature = "std")]
+pub use clock::SystemClock;
+#[cfg(feature = "tokio")]
+pub use clock::TokioClock;
+#[cfg(feature = "std")]
+pub use clock::WallClock;
+#[cfg(feature = "std")]
+pub use error::BuildError;
+#[cfg(feature = "std")]
+pub use error::EnqueueError;
+#[cfg(feature = "std")]
+pub use error::TryEnqueueError;
+#[cfg(feature = "std")]
+pub use queue::CloseMode;
+#[cfg(feature = "std")]
+pub use queue::Handle;
+#[cfg(feature = "std")]
+pub use queue::Lease;
+#[cfg(feature = "std")]
+pub use queue::TimedQueue;
+#[cfg(feature = "std")]
+pub use retry::Attempt;
+#[cfg(feature = "std")]
+pub use retry::RetryPolicy;
+#[cfg(feature = "std")]
+pub use sharded::ShardOrder;
+#[cfg(feature = "std")]
+pub use sharded::ShardedTimedQueue;
+#[cfg(feature = "std")]
+pub use stream::TimedQueueStream;
+#[cfg(feature = "async-std")]
+pub use timer::AsyncStdTimer;
+#[cfg(feature = "std")]
+pub use timer::Sleep;
+#[cfg(feature = "smol")]
+pub use timer::SmolTimer;
+#[cfg(feature = "std")]
+pub use timer::ThreadTimer;
+#[cfg(feature = "std")]
+pub use timer::Timer;
+#[cfg(feature = "tokio")]
+pub use timer::TokioTimer;
@@ -0,0 +1,850 @@
+use std::sync::atomic::AtomicUsize;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::sync::Weak;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+
+use event_listener::Event;
+use event_listener::IntoNotification;
+
+use crate::backend::Backend;
+use crate::clock::add_saturating;
+use crate::clock::round_up;
+use crate::clock::Wake;
+use crate::timer;
+use crate::Clock;
+use crate::EnqueueError;
+use crate::Key;
+use crate::RetryPolicy;
+use crate::TimedQueueBuilder;
+use crate::TimedQueueStream;
+use crate::Timer;
+use crate::TryEnqueueError;
+
+/// Items along with their expirations, as passed to `enqueue_many`.
+pub(crate) type Items<T> = Vec<(T, Option<Instant>)>;
+
+pub(crate) struct State<T> {
+    pub(crate) items: Backend<T>,
+    closed: Option<CloseMode>,
+    /// The origin and length of the windows within which expirations are coalesced, if any.
+    /// See `TimedQueueBuilder::resolution`.
+    window: Option<(Instant, Duration)>,
+}
+
+impl<T> State<T> {
+    pub(crate) fn new(items: Backend<T>, window: Option<(Instant, Duration)>) -> Self {
+        Self {
+            items,
+            closed: None,
+            window,
+        }
+    }
+
+    /// Whether consumers should stop waiting for further items.
+    pub(crate) fn is_finished(&self) -> bool {
+        match self.closed {
+            None => false,
+            Some(CloseMode::Drain) => self.items.is_empty(),
+            Some(CloseMode::Immediate) => true,
+        }
+    }
+
+    /// Adds `t` to the queue unless it is closed or holds `limit` items already.
+    fn try_insert(
+        &mut self,
+        t: T,
+        expiration: Option<Instant>,
+        priority: u32,
+        limit: Option<usize>,
+    ) -> Result<Key, TryEnqueueError<T>> {
+        if self.closed.is_some() {
+            return Err(TryEnqueueError::Closed(t));
+        }
+        if matches!(limit, Some(limit) if self.items.len() >= limit) {
+            return Err(TryEnqueueError::Full(t));
+        }
+        Ok(self.items.push_with_priority(t, expiration, priority))
+    }
+
+    /// Pops the next item if it is due at `now`; otherwise returns how long until it will be, if anything is queued.
+    pub(crate) fn peek_inner(
+        &mut self,
+        now: Instant,
+    ) -> Result<(T, Option<Instant>), Option<Duration>> {
+        match self.items.pop_due(now) {
+            Some(item) => Ok(item),
+            None => Err(self.wait_duration(now)),
+        }
+    }
+
+    /// Leaves the next item in place if it is due at `now`, rescheduled to `now + visibility`, and returns a copy
+    /// of it along with its new `seq` and whether consumers have to wake up for the new expiration. Otherwise
+    /// returns how long until an item will be due, as `peek_inner` does.
+    fn lease_inner(
+        &mut self,
+        now: Instant,
+        visibility: Duration,
+    ) -> Result<(T, Key, u64, bool), Option<Duration>>
+    where
+        T: Clone,
+    {
+        let key = match self.items.due_key(now) {
+            Some(key) => key,
+            None => return Err(self.wait_duration(now)),
+        };
+        let t = self.items.get(key).unwrap().clone();
+        self.items
+            .reschedule(key, Some(add_saturating(now, visibility)));
+        let seq = self.items.seq(key).unwrap();
+        Ok((t, key, seq, self.items.is_pending_head(key)))
+    }
+
+    /// How long until an item will be due, if nothing is due at `now`.
+    fn wait_duration(&mut self, now: Instant) -> Option<Duration> {
+        let next = self
+            .items
+            .next_wakeup(now)
+            .map(|expiration| match self.window {
+                Some((origin, length)) => round_up(expiration, origin, length),
+                None => expiration,
+            });
+        next.map(|expiration| expiration.saturating_duration_since(now))
+    }
+
+    /// Pops up to `max` items that are due at `now`, in order.
+    fn drain_due(&mut self, now: Instant, max: usize) -> Vec<(T, Option<Instant>)> {
+        let mut items = Vec::new();
+        while items.len() < max {
+            match self.items.pop_due(now) {
+                Some(item) => items.push(item),
+                None => break,
+            }
+        }
+        items
+    }
+}
+
+pub(crate) struct SharedInner<T> {
+    pub(crate) storage: Mutex<State<T>>,
+    /// Shared between the shards of a `ShardedTimedQueue`.
+    pub(crate) notify: Arc<Event>,
+    /// Paired with `storage`, for consumers blocking outside of an async runtime.
+    condvar: Condvar,
+    /// Number of live `TimedSender`s, if the queue was created by `channel`.
+    pub(crate) senders: AtomicUsize,
+    capacity_limit: Option<usize>,
+    /// Like `notify` and `condvar`, but for producers waiting for space in a queue with a `capacity_limit`.
+    space_notify: Event,
+    space_condvar: Condvar,
+    pub(crate) clock: Arc<dyn Clock>,
+    /// What async consumers sleep on.
+    pub(crate) timer: Arc<dyn Timer>,
+    /// How long `retry` waits before each attempt.
+    pub(crate) retry_policy: RetryPolicy,
+}
+
+/// A set of objects, each returned no earlier than its expiration.
+///
+/// Items are returned in order of expiration, with `None` (meaning "as soon as possible") first. Items with
+/// equal expirations are returned in the order they were enqueued (or last rescheduled); `T` itself is never
+/// compared, so it need not implement `Ord`. Among items that are already due, those enqueued with a higher
+/// priority (see `enqueue_with_priority`) come first.
+pub struct TimedQueue<T> {
+    pub(crate) inner: Arc<SharedInner<T>>,
+}
+
+/// How consumers behave once a `TimedQueue` is closed. In both cases, no further items can be enqueued.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CloseMode {
+    /// Items already in the queue are still returned as they become due; `dequeue` returns `None` once they
+    /// have all been returned (or cancelled).
+    Drain,
+    /// `dequeue` returns `None` as soon as no item is due, rather than waiting for the rest.
+    /// Items that are not yet due stay in the queue.
+    Immediate,
+}
+
+/// Refers to an item in a `TimedQueue`, and allows withdrawing it before it is dequeued.
+///
+/// A handle does not keep the queue alive.
+pub struct Handle<T> {
+    inner: Weak<SharedInner<T>>,
+    id: Key,
+}
+
+/// A claim on an item returned by `TimedQueue::lease`, which stays in the queue until the lease is acknowledged.
+///
+/// If neither `ack` nor `nack` is called before the lease's visibility timeout, e.g. because the consumer crashed
+/// or its task was cancelled, the item becomes due again and is leased to another consumer; this lease then no
+/// longer has any effect. Dropping a lease leaves the item to reappear in the same way. Like a `Handle`, a lease
+/// does not keep the queue alive.
+pub struct Lease<T> {
+    pub(crate) inner: Weak<SharedInner<T>>,
+    pub(crate) id: Key,
+    /// The item's `seq` when it was leased, which changes if it is leased again or otherwise rescheduled.
+    pub(crate) seq: u64,
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            id: self.id,
+        }
+    }
+}
+
+/// Caps `duration`, the time until the next item is due (if any), at the time left until `deadline` (if any).
+/// Returns `None` if the deadline has already passed.
+pub(crate) fn cap_at_deadline(
+    duration: Option<Duration>,
+    now: Instant,
+    deadline: Option<Instant>,
+) -> Option<Option<Duration>> {
+    match deadline {
+        Some(deadline) if deadline <= now => None,
+        Some(deadline) => Some(Some(
+            duration.map_or(deadline - now, |d| d.min(deadline - now)),
+        )),
+        None => Some(duration),
+    }
+}
+
+impl<T> SharedInner<T> {
+    pub(crate) fn new(
+        state: State<T>,
+        capacity_limit: Option<usize>,
+        clock: Arc<dyn Clock>,
+        timer: Arc<dyn Timer>,
+        retry_policy: RetryPolicy,
+    ) -> Self {
+        Self {
+            storage: Mutex::new(state),
+            notify: Arc::new(Event::new()),
+            condvar: Condvar::new(),
+            senders: AtomicUsize::new(0),
+            capacity_limit,
+            space_notify: Event::new(),
+            space_condvar: Condvar::new(),
+            clock,
+            timer,
+            retry_policy,
+        }
+    }
+
+    /// How long to wait for `duration` to pass on the clock, or `None` to wait until woken.
+    pub(crate) fn sleep_duration(&self, duration: Option<Duration>) -> Option<Duration> {
+        duration.and_then(|duration| self.clock.sleep_duration(duration))
+    }
+
+    /// Runs `f` on the locked state, then lets producers know about any space it freed up.
+    pub(crate) fn with_state<R>(&self, f: impl FnOnce(&mut State<T>) -> R) -> R {
+        let mut lock = self.storage.lock().unwrap();
+        let len = lock.items.len();
+        let result = f(&mut lock);
+        let freed = len.saturating_sub(lock.items.len());
+        drop(lock);
+        self.wake_producers(freed);
+        result
+    }
+
+    /// Reschedules the item to the expiration `schedule` returns, which may also update the item, provided that
+    /// it is still in the queue and, if it is leased, that `lease` is the latest lease on it.
+    pub(crate) fn reschedule<F>(&self, key: Key, lease: Option<u64>, schedule: F) -> bool
+    where
+        F: FnOnce(&mut T) -> Option<Instant>,
+    {
+        let wake = {
+            let mut lock = self.storage.lock().unwrap();
+            if lease.is_some() && lock.items.seq(key) != lease {
+                return false;
+            }
+            let expiration = match lock.items.get_mut(key) {
+                Some(t) => schedule(t),
+                None => return false,
+            };
+            lock.items.reschedule(key, expiration);
+            // Consumers sleep toward the next expiration, so they only need waking if it changed.
+            lock.items.is_pending_head(key)
+        };
+        if wake {
+            self.wake_one();
+        }
+        true
+    }
+
+    fn wake_one(&self) {
+        // `additional`, so that each call wakes another consumer even if an earlier one has not run yet.
+        self.notify.notify(1.additional());
+        self.condvar.notify_one();
+    }
+
+    /// Wakes up to `count` consumers at once.
+    fn wake_many(&self, count: usize) {
+        match count {
+            0 => {}
+            1 => self.wake_one(),
+            _ => {
+                self.notify.notify(count.additional());
+                self.condvar.notify_all();
+            }
+        }
+    }
+
+    fn wake_all(&self) {
+        self.notify.notify(usize::MAX);
+        self.condvar.notify_all();
+        self.space_notify.notify(usize::MAX);
+        self.space_condvar.notify_all();
+    }
+
+    pub(crate) fn wake_producers(&self, freed: usize) {
+        if self.capacity_limit.is_none() || freed == 0 {
+            return;
+        }
+        self.space_notify.notify(freed.additional());
+        self.space_condvar.notify_all();
+    }
+}
+
+impl<T> Wake for SharedInner<T>
+where
+    T: Send,
+{
+    fn wake(&self) {
+        // Blocking consumers check the time with the lock held, so taking it here ensures they are either
+        // already waiting on the condvar, or will see the new time.
+        drop(self.storage.lock().unwrap());
+        self.notify.notify(usize::MAX);
+        self.condvar.notify_all();
+    }
+}
+
+impl<T> Handle<T> {
+    /// Removes the item from the queue, returning it if it was still pending.
+    ///
+    /// Returns `None` if the item has already been dequeued or cancelled, or if the queue no longer exists.
+    pub fn cancel(&self) -> Option<T> {
+        let inner = self.inner.upgrade()?;
+        inner.with_state(|state| {
+            let t = state.items.remove(self.id)?;
+            if state.is_finished() {
+                // This was the last item holding consumers of a draining queue.
+                inner.wake_all();
+            }
+            Some(t)
+        })
+    }
+
+    /// Moves the item to a new expiration, earlier or later. See `TimedQueue::reschedule`.
+    pub fn reschedule(&self, expiration: Option<Instant>) -> bool {
+        match self.inner.upgrade() {
+            Some(inner) => inner.reschedule(self.id, None, |_| expiration),
+            None => false,
+        }
+    }
+
+    /// Returns whether the item is still waiting in the queue.
+    pub fn is_pending(&self) -> bool {
+        match self.inner.upgrade() {
+            Some(inner) => inner.storage.lock().unwrap().items.contains(self.id),
+            None => false,
+        }
+    }
+}
+
+impl<T> Lease<T> {
+    /// Removes the item from the queue, now that it has been processed.
+    ///
+    /// Returns `false` (and does nothing) if the lease expired and the item was leased again, if the item was
+    /// cancelled or rescheduled in the meantime, or if the queue no longer exists.
+    pub fn ack(self) -> bool {
+        let inner = match self.inner.upgrade() {
+            Some(inner) => inner,
+            None => return false,
+        };
+        inner.with_state(|state| {
+            if state.items.seq(self.id) != Some(self.seq) {
+                return false;
+            }
+            state.items.remove(self.id);
+            if state.is_finished() {
+                // This was the last item holding consumers of a draining queue.
+                inner.wake_all();
+            }
+            true
+        })
+    }
+
+    /// Gives the item back, to become due again after `delay` instead of at the end of the visibility timeout.
+    ///
+    /// Returns `false` (and does nothing) in the same cases as `ack`.
+    pub fn nack(self, delay: Duration) -> bool {
+        match self.inner.upgrade() {
+            Some(inner) => {
+                let expiration = add_saturating(inner.clock.now(), delay);
+                inner.reschedule(self.id, Some(self.seq), |_| Some(expiration))
+            }
+            None => false,
+        }
+    }
+}
+
+impl<T> Clone for TimedQueue<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> Default for TimedQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> TimedQueue<T> {
+    /// Creates an empty queue using the default clock and timer.
+    ///
+    /// With the `tokio` feature (the default) these are `TokioClock` and `TokioTimer`, so that the queue works
+    /// under tokio's paused time. Otherwise the clock is `SystemClock`, and the timer is `AsyncStdTimer` or
+    /// `SmolTimer` if the corresponding feature is enabled, or else `ThreadTimer`.
+    pub fn new() -> Self {
+        Self::builder().build().unwrap()
+    }
+
+    /// Returns a builder, to configure several of the options below at once.
+    pub fn builder() -> TimedQueueBuilder<T> {
+        TimedQueueBuilder::new()
+    }
+
+    /// Creates a queue that compares expirations against `clock` instead of the default clock.
+    /// Durations passed to the queue, such as timeouts, are measured by `clock` too.
+    pub fn with_clock<C>(clock: C) -> Self
+    where
+        C: Clock,
+        T: Send + 'static,
+    {
+        Self::builder().clock(clock).build().unwrap()
+    }
+
+    /// Creates a queue whose async consumers sleep on `timer` instead of the default timer, e.g. to run on
+    /// a runtime other than the one selected by cargo features.
+    pub fn with_timer<M>(timer: M) -> Self
+    where
+        M: Timer,
+    {
+        Self::builder().timer(timer).build().unwrap()
+    }
+
+    /// Creates a queue that holds at most `limit` items at a time. See `enqueue` and `enqueue_wait`.
+    ///
+    /// # Panics
+    /// Panics if `limit` is zero.
+    pub fn with_capacity_limit(limit: usize) -> Self {
+        Self::builder()
+            .capacity_limit(limit)
+            .build()
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Creates a queue that keeps items in a hierarchical timing wheel rather than a binary heap, for when
+    /// there are very many of them.
+    ///
+    /// Enqueueing, rescheduling and cancelling then take constant time rather than time logarithmic in the
+    /// number of items. In exchange, expirations are rounded up to the next multiple of `resolution` (counted
+    /// from the queue's creation), so items may be returned up to `resolution` late, and `peek_deadline`
+    /// returns the rounded-up expiration.
+    ///
+    /// # Panics
+    /// Panics if `resolution` is zero.
+    pub fn with_timing_wheel(resolution: Duration) -> Self {
+        Self::builder()
+            .timing_wheel(resolution)
+            .build()
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Adds `t` to the queue, to be returned no earlier than `expiration` (or as soon as possible if `None`).
+    ///
+    /// The returned handle can be used to cancel the item; it may simply be dropped otherwise.
+    /// Fails, handing `t` back, if the queue has been closed or is at its capacity limit. To wait for space
+    /// instead, use `enqueue_wait`, or `enqueue_blocking` outside of async code.
+    pub fn enqueue(
+        &self,
+        t: T,
+        expiration: Option<Instant>,
+    ) -> Result<Handle<T>, TryEnqueueError<T>> {
+        self.enqueue_with_priority(t, expiration, 0)
+    }
+
+    /// Like `enqueue`, with an expiration `delay` from now according to the queue's clock.
+    ///
+    /// A delay too large to represent is treated as "far in the future" rather than overflowing.
+    pub fn enqueue_after(&self, t: T, delay: Duration) -> Result<Handle<T>, TryEnqueueError<T>> {
+        let expiration = add_saturating(self.inner.clock.now(), delay);
+        self.enqueue(t, Some(expiration))
+    }
+
+    /// Like `enqueue`, with the current time as expiration. Unlike an expiration of `None`, this places the
+    /// item after others that are already due.
+    pub fn enqueue_now(&self, t: T) -> Result<Handle<T>, TryEnqueueError<T>> {
+        self.enqueue(t, Some(self.inner.clock.now()))
+    }
+
+    /// Like `enqueue`, but with a wall-clock expiration, converted with `Clock::instant_from_system_time`.
+    ///
+    /// Use a queue with a `WallClock` if the expiration should follow later changes to the system time.
+    pub fn enqueue_at_system_time(
+        &self,
+        t: T,
+        expiration: SystemTime,
+    ) -> Result<Handle<T>, TryEnqueueError<T>> {
+        let expiration = self.inner.clock.instant_from_system_time(expiration);
+        self.enqueue(t, Some(expiration))
+    }
+
+    /// Converts an expiration returned by this queue to wall-clock time, e.g. to persist it.
+    pub fn to_system_time(&self, expiration: Instant) -> SystemTime {
+        self.inner.clock.system_time_from_instant(expiration)
+    }
+
+    /// Like `enqueue`, but among items that are due, those with a higher `priority` are returned first.
+    ///
+    /// Priority only matters once items are due; it never causes an item to be returned before its expiration.
+    /// Items enqueued without a priority have priority 0.
+    pub fn enqueue_with_priority(
+        &self,
+        t: T,
+        expiration: Option<Instant>,
+        priority: u32,
+    ) -> Result<Handle<T>, TryEnqueueError<T>> {
+        let id = self.inner.storage.lock().unwrap().try_insert(
+            t,
+            expiration,
+            priority,
+            self.inner.capacity_limit,
+        )?;
+        self.inner.wake_one();
+        Ok(self.handle(id))
+    }
+
+    /// Adds every item in `items`, as if by `enqueue`, but taking the lock once and waking only as many consumers as
+    /// there are items due, which is much faster for large batches. The heap is rebuilt in time linear in its new
+    /// size when that beats inserting the items one by one.
+    ///
+    /// If the queue is closed, returns every item as `Closed`. With a capacity limit, adds as many items as fit
+    /// and returns the rest as `Full`.
+    pub fn enqueue_many<I>(&self, items: I) -> Result<(), TryEnqueueError<Items<T>>>
+    where
+        I: IntoIterator<Item = (T, Option<Instant>)>,
+    {
+        let mut items: Vec<_> = items.into_iter().collect();
+        let mut lock = self.inner.storage.lock().unwrap();
+        if lock.closed.is_some() {
+            return Err(TryEnqueueError::Closed(items));
+        }
+        let rest = match self.inner.capacity_limit {
+            Some(limit) if lock.items.len() + items.len() > limit => {
+                items.split_off(limit.saturating_sub(lock.items.len()))
+            }
+            _ => Vec::new(),
+        };
+        let now = self.inner.clock.now();
+        // How many of the items are due, and the earliest expiration of the others.
+        let mut due = 0;
+        let mut earliest = None;
+        for (_, expiration) in &items {
+            match *expiration {
+                Some(expiration) if expiration > now => {
+                    earliest = Some(earliest.map_or(expiration, |e: Instant| e.min(expiration)))
+                }
+                _ => due += 1,
+            }
+        }
+        // As for `enqueue`, a consumer sleeping toward the next expiration has to wake up to sleep toward an
+        // earlier one.
+        let earlier = match (earliest, lock.items.next_wakeup(now)) {
+            (Some(earliest), Some(next)) => earliest < next,
+            (earliest, _) => earliest.is_some(),
+        };
+        lock.items.extend(items);
+        drop(lock);
+        self.inner.wake_many(due + earlier as usize);
+        if rest.is_empty() {
+            Ok(())
+        } else {
+            Err(TryEnqueueError::Full(rest))
+        }
+    }
+
+    /// Same as `enqueue`, named to contrast with `enqueue_wait` and `enqueue_blocking`.
+    pub fn try_enqueue(
+        &self,
+        t: T,
+        expiration: Option<Instant>,
+    ) -> Result<Handle<T>, TryEnqueueError<T>> {
+        self.enqueue(t, expiration)
+    }
+
+    /// Like `enqueue`, but waits asynchronously for space if the queue is at its capacity limit.
+    pub async fn enqueue_wait(
+        &self,
+        t: T,
+        expiration: Option<Instant>,
+    ) -> Result<Handle<T>, EnqueueError<T>> {
+        let mut t = t;
+        loop {
+            // Created before trying, so that space freed up in between is not missed.
+            let notified = self.inner.space_notify.listen();
+            match self.try_enqueue(t, expiration) {
+                Ok(handle) => return Ok(handle),
+                Err(TryEnqueueError::Full(rejected)) => t = rejected,
+                Err(TryEnqueueError::Closed(rejected)) => return Err(EnqueueError(rejected)),
+            }
+            notified.await;
+        }
+    }
+
+    /// Like `enqueue_wait`, but parks the current thread instead of awaiting, so it can be used outside of an async
+    /// runtime. Calling it from async code can deadlock, as the consumers that would free up space may never run.
+    pub fn enqueue_blocking(
+        &self,
+        t: T,
+        expiration: Option<Instant>,
+    ) -> Result<Handle<T>, EnqueueError<T>> {
+        let mut t = t;
+        let mut lock = self.inner.storage.lock().unwrap();
+        let id = loop {
+            match lock.try_insert(t, expiration, 0, self.inner.capacity_limit) {
+                Ok(id) => break id,
+                Err(TryEnqueueError::Full(rejected)) => {
+                    t = rejected;
+                    lock = self.inner.space_condvar.wait(lock).unwrap();
+                }
+                Err(TryEnqueueError::Closed(rejected)) => return Err(EnqueueError(rejected)),
+            }
+        };
+        drop(lock);
+        self.inner.wake_one();
+        Ok(self.handle(id))
+    }
+
+    fn handle(&self, id: Key) -> Handle<T> {
+        Handle {
+            inner: Arc::downgrade(&self.inner),
+            id,
+        }
+    }
+
+    /// Returns the maximum number of items the queue can hold, if it was created with one.
+    pub fn capacity_limit(&self) -> Option<usize> {
+        self.inner.capacity_limit
+    }
+
+    /// Returns the policy `retry` and `enqueue_retry` follow.
+    pub fn retry_policy(&self) -> RetryPolicy {
+        self.inner.retry_policy
+    }
+
+    /// Closes the queue, so that further calls to `enqueue` fail, and wakes every waiting consumer and producer.
+    /// See `CloseMode` for what happens to the items still in the queue.
+    ///
+    /// Closing an already closed queue can switch it from `Drain` to `Immediate`, but not back.
+    pub fn close(&self, mode: CloseMode) {
+        {
+            let mut lock = self.inner.storage.lock().unwrap();
+            if lock.closed != Some(CloseMode::Immediate) {
+                lock.closed = Some(mode);
+            }
+        }
+        self.inner.wake_all();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.inner.storage.lock().unwrap().closed.is_some()
+    }
+
+    /// Moves a pending item to a new expiration, earlier or later, waking a consumer if it is now the next item due.
+    ///
+    /// Returns `false` (and does nothing) if the item is no longer in the queue.
+    pub fn reschedule(&self, handle: &Handle<T>, expiration: Option<Instant>) -> bool {
+        Weak::ptr_eq(&handle.inner, &Arc::downgrade(&self.inner))
+            && self.inner.reschedule(handle.id, None, |_| expiration)
+    }
+
+    fn peek_inner(&self) -> Result<(T, Option<Instant>), Option<Duration>> {
+        let now = self.inner.clock.now();
+        self.inner.with_state(|state| state.peek_inner(now))
+    }
+
+    /// Removes and returns the next item if it is due, without waiting.
+    pub fn try_dequeue(&self) -> Option<(T, Option<Instant>)> {
+        self.peek_inner().ok()
+    }
+
+    /// Returns the expiration of the next item without removing it, or `None` if the queue is empty.
+    pub fn peek_deadline(&self) -> Option<Option<Instant>> {
+        let now = self.inner.clock.now();
+        self.inner.storage.lock().unwrap().items.peek(now)
+    }
+
+    /// Returns how long until the next item is due (zero if one already is), or `None` if the queue is empty.
+    pub fn next_ready_in(&self) -> Option<Duration> {
+        let now = self.inner.clock.now();
+        match self.peek_deadline()? {
+            Some(expiration) => Some(expiration.saturating_duration_since(now)),
+            None => Some(Duration::from_secs(0)),
+        }
+    }
+
+    /// Waits for the next item to become due and returns it, or returns `None` once the queue is closed
+    /// (see `CloseMode`).
+    pub async fn dequeue(&self) -> Option<(T, Option<Instant>)> {
+        self.dequeue_inner(None).await
+    }
+
+    /// Like `dequeue`, but gives up and returns `None` if no item becomes due within `duration`.
+    pub async fn dequeue_timeout(&self, duration: Duration) -> Option<(T, Option<Instant>)> {
+        self.dequeue_inner(self.inner.clock.now().checked_add(duration))
+            .await
+    }
+
+    /// Like `dequeue`, but gives up and returns `None` if no item becomes due by `deadline`.
+    ///
+    /// An item that is due at the deadline is still returned.
+    pub async fn dequeue_until(&self, deadline: Instant) -> Option<(T, Option<Instant>)> {
+        self.dequeue_inner(Some(deadline)).await
+    }
+
+    /// Waits for at least one item to become due, then returns up to `max` due items at once.
+    ///
+    /// Returns an empty `Vec` immediately if `max` is zero, and once the queue is closed.
+    pub async fn dequeue_batch(&self, max: usize) -> Vec<(T, Option<Instant>)> {
+        if max == 0 {
+            return Vec::new();
+        }
+        let take = |state: &mut State<T>, now| {
+            let first = state.peek_inner(now)?;
+            let mut items = vec![first];
+            items.extend(state.drain_due(now, max - 1));
+            Ok(items)
+        };
+        self.wait_for(None, take).await.unwrap_or_default()
+    }
+
+    /// Removes and returns every item that is currently due, without waiting.
+    pub fn drain_due(&self) -> Vec<(T, Option<Instant>)> {
+        let now = self.inner.clock.now();
+        self.inner
+            .with_state(|state| state.drain_due(now, usize::MAX))
+    }
+
+    /// Waits for the next item to become due and returns a copy of it, leaving the item in the queue until the
+    /// returned `Lease` is acknowledged, for at-least-once processing.
+    ///
+    /// Meanwhile the item is rescheduled to `visibility` from now, when it becomes due again unless the lease is
+    /// acknowledged with `Lease::ack` or handed back with `Lease::nack`. Returns `None` once the queue is closed;
+    /// a queue closed with `CloseMode::Drain` is only finished once every leased item has been acknowledged.
+    pub async fn lease(&self, visibility: Duration) -> Option<(T, Lease<T>)>
+    where
+        T: Clone,
+    {
+        let (t, id, seq, wake) = self
+            .wait_for(None, |state, now| state.lease_inner(now, visibility))
+            .await?;
+        if wake {
+            self.inner.wake_one();
+        }
+        let lease = Lease {
+            inner: Arc::downgrade(&self.inner),
+            id,
+            seq,
+        };
+        Some((t, lease))
+    }
+
+    async fn dequeue_inner(&self, deadline: Option<Instant>) -> Option<(T, Option<Instant>)> {
+        self.wait_for(deadline, State::peek_inner).await
+    }
+
+    /// Calls `take` until it succeeds, `deadline` passes or the queue is finished, sleeping in between until
+    /// the duration it returns elapses or the queue changes.
+    async fn wait_for<R, F>(&self, deadline: Option<Instant>, mut take: F) -> Option<R>
+    where
+        F: FnMut(&mut State<T>, Instant) -> Result<R, Option<Duration>>,
+    {
+        loop {
+            // Created before looking at the queue, so that a `close` in between is not missed.
+            let notified = self.inner.notify.listen();
+            let now = self.inner.clock.now();
+            let result = match self.inner.with_state(|state| match take(state, now) {
+                Err(_) if state.is_finished() => None,
+                result => Some(result),
+            }) {
+                Some(result) => result,
+                None => return None,
+            };
+            let duration = match result {
+                Ok(result) => break Some(result),
+                Err(duration) => match cap_at_deadline(duration, now, deadline) {
+                    Some(duration) => duration,
+                    None => break None,
+                },
+            };
+            match self.inner.sleep_duration(duration) {
+                Some(duration) => {
+                    timer::race(notified, self.inner.timer.sleep(duration)).await;
+                }
+                None => {
+                    notified.await;
+                }
+            }
+        }
+    }
+
+    /// Returns a `Stream` yielding items as they become due, sharing this queue.
+    pub fn stream(&self) -> TimedQueueStream<T> {
+        TimedQueueStream::new(self.clone())
+    }
+
+    /// Like `stream`, but consumes this handle to the queue.
+    pub fn into_stream(self) -> TimedQueueStream<T> {
+        TimedQueueStream::new(self)
+    }
+
+    /// Like `dequeue`, but parks the current thread instead of awaiting, so it can be used outside of an async runtime.
+    pub fn dequeue_blocking(&self) -> Option<(T, Option<Instant>)> {
+        self.dequeue_blocking_until(None)
+    }
+
+    /// Like `dequeue_blocking`, but gives up and returns `None` if no item becomes due within `timeout`.
+    pub fn dequeue_blocking_timeout(&self, timeout: Duration) -> Option<(T, Option<Instant>)> {
+        self.dequeue_blocking_until(self.inner.clock.now().checked_add(timeout))
+    }
+
+    fn dequeue_blocking_until(&self, deadline: Option<Instant>) -> Option<(T, Option<Instant>)> {
+        let mut lock = self.inner.storage.lock().unwrap();
+        loop {
+            let now = self.inner.clock.now();
+            let wait = match lock.peek_inner(now) {
+                Ok(item) => {
+                    drop(lock);
+                    self.inner.wake_producers(1);
+                    return Some(item);
+                }
+                Err(_) if lock.is_finished() => return None,
+                Err(duration) => cap_at_deadline(duration, now, deadline)?,
+            };
+            lock = match self.inner.sleep_duration(wait) {
+                Some(wait) => self.inner.condvar.wait_timeout(lock, wait).unwrap().0,
+                None => self.inner.condvar.wait(lock).unwrap(),
+            };
+        }
+    }
+}
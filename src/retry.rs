@@ -0,0 +1,165 @@
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::convert::TryFrom;
+use std::hash::BuildHasher;
+use std::hash::Hasher;
+use std::time::Duration;
+
+use crate::clock::add_saturating;
+use crate::Handle;
+use crate::Lease;
+use crate::TimedQueue;
+use crate::TryEnqueueError;
+
+/// How long to wait before each retry of an item. See `TimedQueue::retry`.
+///
+/// Attempts are numbered from 0, the first try, which is never delayed; the delays below are those before
+/// attempt 1 and later.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RetryPolicy {
+    /// Waits the same time before every retry.
+    Fixed(Duration),
+    /// Waits `initial` before the first retry, and `increment` longer before each one after that.
+    Linear {
+        initial: Duration,
+        increment: Duration,
+    },
+    /// Waits `initial` before the first retry, and twice as long before each one after that, up to `max`.
+    Exponential { initial: Duration, max: Duration },
+    /// Waits a random time between `base` and three times the previous delay, up to `max`. Spreads out the retries
+    /// of items that failed together, rather than having them all retried at the same time again.
+    DecorrelatedJitter { base: Duration, max: Duration },
+}
+
+impl Default for RetryPolicy {
+    /// Exponential backoff from one second, up to an hour.
+    fn default() -> Self {
+        RetryPolicy::Exponential {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns how long to wait before attempt number `attempt`, given the delay before the previous attempt,
+    /// if known. Only `DecorrelatedJitter` depends on the previous delay, taking `base` if it is unknown.
+    pub fn delay(&self, attempt: u32, previous: Option<Duration>) -> Duration {
+        let retries = match attempt.checked_sub(1) {
+            Some(retries) => retries,
+            None => return Duration::from_secs(0),
+        };
+        match *self {
+            RetryPolicy::Fixed(delay) => delay,
+            RetryPolicy::Linear { initial, increment } => {
+                initial.saturating_add(increment.saturating_mul(retries))
+            }
+            RetryPolicy::Exponential { initial, max } => {
+                let delay = match 2u32.checked_pow(retries) {
+                    Some(factor) => initial.saturating_mul(factor),
+                    None if initial == Duration::from_secs(0) => initial,
+                    None => Duration::MAX,
+                };
+                delay.min(max)
+            }
+            RetryPolicy::DecorrelatedJitter { base, max } => {
+                let upper = previous.unwrap_or(base).saturating_mul(3);
+                random_between(base, upper).min(max)
+            }
+        }
+    }
+}
+
+thread_local! {
+    /// Xorshift state, seeded differently on every thread so that retries are not synchronized across them.
+    static RNG: Cell<u64> = Cell::new(RandomState::new().build_hasher().finish() | 1);
+}
+
+/// A uniformly distributed duration from `low` to `high` inclusive, or `low` if `high` is not greater.
+fn random_between(low: Duration, high: Duration) -> Duration {
+    let span = match high.checked_sub(low) {
+        Some(span) => u64::try_from(span.as_nanos()).unwrap_or(u64::MAX),
+        None => return low,
+    };
+    let random = RNG.with(|rng| {
+        let mut x = rng.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        rng.set(x);
+        x
+    });
+    let offset = match span.checked_add(1) {
+        Some(range) => random % range,
+        None => random,
+    };
+    low.saturating_add(Duration::from_nanos(offset))
+}
+
+/// An item being retried, along with the number of the attempt it is queued for. See `TimedQueue::retry`.
+#[derive(Clone, Debug)]
+pub struct Attempt<T> {
+    pub item: T,
+    /// 0 for the first try, 1 for the first retry, and so on.
+    pub attempt: u32,
+    /// The delay before this attempt, which `DecorrelatedJitter` bases the next one on.
+    delay: Duration,
+}
+
+impl<T> Attempt<T> {
+    pub fn into_inner(self) -> T {
+        self.item
+    }
+
+    /// Moves on to the next attempt, returning how long to wait before it.
+    fn advance(&mut self, policy: &RetryPolicy) -> Duration {
+        self.attempt = self.attempt.saturating_add(1);
+        self.delay = policy.delay(self.attempt, Some(self.delay));
+        self.delay
+    }
+}
+
+impl<T> TimedQueue<Attempt<T>> {
+    /// Enqueues `t` for attempt number `attempt`, after the delay the queue's `RetryPolicy` gives for it. With
+    /// `attempt` 0, the item is due right away.
+    pub fn enqueue_retry(
+        &self,
+        t: T,
+        attempt: u32,
+    ) -> Result<Handle<Attempt<T>>, TryEnqueueError<Attempt<T>>> {
+        let delay = self.inner.retry_policy.delay(attempt, None);
+        let item = Attempt {
+            item: t,
+            attempt,
+            delay,
+        };
+        self.enqueue_after(item, delay)
+    }
+
+    /// Enqueues a dequeued item again for its next attempt, after the delay the queue's `RetryPolicy` gives for it.
+    pub fn retry(
+        &self,
+        item: Attempt<T>,
+    ) -> Result<Handle<Attempt<T>>, TryEnqueueError<Attempt<T>>> {
+        let mut item = item;
+        let delay = item.advance(&self.inner.retry_policy);
+        self.enqueue_after(item, delay)
+    }
+}
+
+impl<T> Lease<Attempt<T>> {
+    /// Like `nack`, but counts the leased item's next attempt and delays it according to the queue's
+    /// `RetryPolicy`.
+    ///
+    /// Returns `false` (and does nothing) in the same cases as `ack`.
+    pub fn retry(self) -> bool {
+        let inner = match self.inner.upgrade() {
+            Some(inner) => inner,
+            None => return false,
+        };
+        let now = inner.clock.now();
+        inner.reschedule(self.id, Some(self.seq), |item| {
+            Some(add_saturating(now, item.advance(&inner.retry_policy)))
+        })
+    }
+}
@@ -0,0 +1,273 @@
+use std::cmp::Reverse;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use event_listener::Event;
+
+use crate::backend::Backend;
+use crate::builder::register;
+use crate::clock::DefaultClock;
+use crate::queue::cap_at_deadline;
+use crate::queue::Items;
+use crate::queue::SharedInner;
+use crate::queue::State;
+use crate::timer;
+use crate::timer::DefaultTimer;
+use crate::Clock;
+use crate::CloseMode;
+use crate::Handle;
+use crate::RetryPolicy;
+use crate::TimedHeap;
+use crate::TimedQueue;
+use crate::Timer;
+use crate::TryEnqueueError;
+
+/// Which item a `ShardedTimedQueue` returns when several are due.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShardOrder {
+    /// The one a `TimedQueue` would return: the highest priority, then the earliest expiration. Consumers lock
+    /// every shard to find it, though producers still only lock one.
+    Exact,
+    /// Any due item, taken from the first shard found to have one. Consumers lock one shard at a time, starting
+    /// from a different shard each time, so items come out roughly, but not exactly, in order.
+    Relaxed,
+}
+
+/// A `TimedQueue` split into several independently locked shards, so that many producer threads do not contend
+/// on a single lock.
+///
+/// Each producer thread enqueues into one shard, so items from the same thread keep the ordering guarantees of a
+/// `TimedQueue` among themselves; across threads, items with equal expirations are not returned in any
+/// particular order. Otherwise `try_dequeue`, `dequeue` and `dequeue_timeout` behave as on a `TimedQueue`,
+/// subject to the `ShardOrder` chosen; the rest of its consumer API (blocking, batches, streams and leases)
+/// is not available on a sharded queue.
+pub struct ShardedTimedQueue<T> {
+    shards: Arc<[TimedQueue<T>]>,
+    order: ShardOrder,
+    /// Where the next `Relaxed` consumer starts looking.
+    next_shard: Arc<AtomicUsize>,
+}
+
+static NEXT_PRODUCER: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// Spreads producer threads evenly over the shards.
+    static PRODUCER: usize = NEXT_PRODUCER.fetch_add(1, Ordering::Relaxed);
+}
+
+impl<T> Clone for ShardedTimedQueue<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shards: self.shards.clone(),
+            order: self.order,
+            next_shard: self.next_shard.clone(),
+        }
+    }
+}
+
+impl<T> ShardedTimedQueue<T> {
+    /// Creates an empty queue with `shards` shards, using the default clock and timer.
+    ///
+    /// # Panics
+    /// Panics if `shards` is zero.
+    pub fn new(shards: usize, order: ShardOrder) -> Self {
+        Self::with_parts(shards, order, Arc::new(DefaultClock::default()))
+    }
+
+    /// Like `new`, but compares expirations against `clock`. See `TimedQueue::with_clock`.
+    ///
+    /// # Panics
+    /// Panics if `shards` is zero.
+    pub fn with_clock<C>(shards: usize, order: ShardOrder, clock: C) -> Self
+    where
+        C: Clock,
+        T: Send + 'static,
+    {
+        let queue = Self::with_parts(shards, order, Arc::new(clock));
+        for shard in queue.shards.iter() {
+            register(shard);
+        }
+        queue
+    }
+
+    fn with_parts(shards: usize, order: ShardOrder, clock: Arc<dyn Clock>) -> Self {
+        assert!(shards > 0, "a sharded queue needs at least one shard");
+        let notify = Arc::new(Event::new());
+        let timer: Arc<dyn Timer> = Arc::new(DefaultTimer::default());
+        let shards = (0..shards)
+            .map(|_| {
+                let mut inner = SharedInner::new(
+                    State::new(Backend::Heap(TimedHeap::new()), None),
+                    None,
+                    clock.clone(),
+                    timer.clone(),
+                    RetryPolicy::default(),
+                );
+                inner.notify = notify.clone();
+                TimedQueue {
+                    inner: Arc::new(inner),
+                }
+            })
+            .collect();
+        Self {
+            shards,
+            order,
+            next_shard: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// The shard the current thread enqueues into.
+    fn shard(&self) -> &TimedQueue<T> {
+        let index = PRODUCER.with(|producer| *producer) % self.shards.len();
+        &self.shards[index]
+    }
+
+    fn inner(&self) -> &SharedInner<T> {
+        &self.shards[0].inner
+    }
+
+    /// See `TimedQueue::enqueue`. The handle refers to the item within its shard.
+    pub fn enqueue(
+        &self,
+        t: T,
+        expiration: Option<Instant>,
+    ) -> Result<Handle<T>, TryEnqueueError<T>> {
+        self.shard().enqueue(t, expiration)
+    }
+
+    /// See `TimedQueue::enqueue_after`.
+    pub fn enqueue_after(&self, t: T, delay: Duration) -> Result<Handle<T>, TryEnqueueError<T>> {
+        self.shard().enqueue_after(t, delay)
+    }
+
+    /// See `TimedQueue::enqueue_many`. The items all go into the same shard.
+    pub fn enqueue_many<I>(&self, items: I) -> Result<(), TryEnqueueError<Items<T>>>
+    where
+        I: IntoIterator<Item = (T, Option<Instant>)>,
+    {
+        self.shard().enqueue_many(items)
+    }
+
+    /// See `TimedQueue::enqueue_with_priority`.
+    pub fn enqueue_with_priority(
+        &self,
+        t: T,
+        expiration: Option<Instant>,
+        priority: u32,
+    ) -> Result<Handle<T>, TryEnqueueError<T>> {
+        self.shard().enqueue_with_priority(t, expiration, priority)
+    }
+
+    /// Closes every shard. See `TimedQueue::close`.
+    pub fn close(&self, mode: CloseMode) {
+        for shard in self.shards.iter() {
+            shard.close(mode);
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.shards[0].is_closed()
+    }
+
+    /// Removes and returns the next item if it is due, without waiting.
+    pub fn try_dequeue(&self) -> Option<(T, Option<Instant>)> {
+        self.take(self.inner().clock.now()).ok()
+    }
+
+    /// See `TimedQueue::dequeue`.
+    pub async fn dequeue(&self) -> Option<(T, Option<Instant>)> {
+        self.dequeue_inner(None).await
+    }
+
+    /// See `TimedQueue::dequeue_timeout`.
+    pub async fn dequeue_timeout(&self, duration: Duration) -> Option<(T, Option<Instant>)> {
+        self.dequeue_inner(self.inner().clock.now().checked_add(duration))
+            .await
+    }
+
+    /// Like `TimedQueue::wait_for`, with the notifications of every shard going to the same `Event`.
+    async fn dequeue_inner(&self, deadline: Option<Instant>) -> Option<(T, Option<Instant>)> {
+        let inner = self.inner();
+        loop {
+            // Created before looking at the shards, so that an enqueue or `close` in between is not missed.
+            let notified = inner.notify.listen();
+            let now = inner.clock.now();
+            let duration = match self.take(now) {
+                Ok(item) => return Some(item),
+                Err(_) if self.is_finished() => return None,
+                Err(duration) => cap_at_deadline(duration, now, deadline)?,
+            };
+            match inner.sleep_duration(duration) {
+                Some(duration) => timer::race(notified, inner.timer.sleep(duration)).await,
+                None => notified.await,
+            }
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.shards
+            .iter()
+            .all(|shard| shard.inner.storage.lock().unwrap().is_finished())
+    }
+
+    /// Pops a due item according to `order`; otherwise returns how long until one will be due, if any.
+    fn take(&self, now: Instant) -> Result<(T, Option<Instant>), Option<Duration>> {
+        match self.order {
+            ShardOrder::Exact => self.take_exact(now),
+            ShardOrder::Relaxed => self.take_relaxed(now),
+        }
+    }
+
+    fn take_exact(&self, now: Instant) -> Result<(T, Option<Instant>), Option<Duration>> {
+        // Always locked in the same order, so that consumers cannot deadlock.
+        let mut states: Vec<_> = self
+            .shards
+            .iter()
+            .map(|shard| shard.inner.storage.lock().unwrap())
+            .collect();
+        let best = states
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, state)| {
+                let (priority, expiration) = state.items.peek_due(now)?;
+                Some(((priority, Reverse(expiration)), index))
+            })
+            .max_by_key(|(key, _)| *key);
+        match best {
+            Some((_, index)) => {
+                let item = states[index].items.pop_due(now).unwrap();
+                drop(states);
+                self.shards[index].inner.wake_producers(1);
+                Ok(item)
+            }
+            None => Err(states
+                .iter_mut()
+                .filter_map(|state| state.items.next_wakeup(now))
+                .min()
+                .map(|expiration| expiration.saturating_duration_since(now))),
+        }
+    }
+
+    fn take_relaxed(&self, now: Instant) -> Result<(T, Option<Instant>), Option<Duration>> {
+        let start = self.next_shard.fetch_add(1, Ordering::Relaxed);
+        let mut next = None;
+        for offset in 0..self.shards.len() {
+            let shard = &self.shards[(start + offset) % self.shards.len()];
+            match shard.inner.with_state(|state| state.peek_inner(now)) {
+                Ok(item) => return Ok(item),
+                Err(Some(duration)) => {
+                    next = Some(next.map_or(duration, |next: Duration| next.min(duration)))
+                }
+                Err(None) => {}
+            }
+        }
+        Err(next)
+    }
+}
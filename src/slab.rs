@@ -0,0 +1,97 @@
+use alloc::vec::Vec;
+
+use crate::Key;
+
+/// Storage for the items of a `TimedHeap` or `TimingWheel`, with constant-time lookup by `Key`.
+///
+/// Slots are reused, so a key also carries the id its item was inserted with; ids must be unique.
+pub(crate) struct Slab<E> {
+    slots: Vec<Slot<E>>,
+    /// Indices of vacant slots.
+    free: Vec<usize>,
+    len: usize,
+}
+
+enum Slot<E> {
+    Occupied { id: u64, entry: E },
+    Vacant,
+}
+
+impl<E> Slab<E> {
+    pub(crate) fn new() -> Self {
+        Self {
+            slots: Vec::new(),
+            free: Vec::new(),
+            len: 0,
+        }
+    }
+
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        Self {
+            slots: Vec::with_capacity(capacity),
+            free: Vec::new(),
+            len: 0,
+        }
+    }
+
+    pub(crate) fn reserve(&mut self, additional: usize) {
+        self.slots
+            .reserve(additional.saturating_sub(self.free.len()));
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn insert(&mut self, id: u64, entry: E) -> Key {
+        let slot = Slot::Occupied { id, entry };
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.slots[index] = slot;
+                index
+            }
+            None => {
+                self.slots.push(slot);
+                self.slots.len() - 1
+            }
+        };
+        self.len += 1;
+        Key { index, id }
+    }
+
+    pub(crate) fn get(&self, key: Key) -> Option<&E> {
+        match self.slots.get(key.index) {
+            Some(Slot::Occupied { id, entry }) if *id == key.id => Some(entry),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn get_mut(&mut self, key: Key) -> Option<&mut E> {
+        match self.slots.get_mut(key.index) {
+            Some(Slot::Occupied { id, entry }) if *id == key.id => Some(entry),
+            _ => None,
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = (Key, &mut E)> {
+        self.slots
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, slot)| match slot {
+                Slot::Occupied { id, entry } => Some((Key { index, id: *id }, entry)),
+                Slot::Vacant => None,
+            })
+    }
+
+    pub(crate) fn remove(&mut self, key: Key) -> Option<E> {
+        self.get(key)?;
+        let slot = core::mem::replace(&mut self.slots[key.index], Slot::Vacant);
+        self.free.push(key.index);
+        self.len -= 1;
+        match slot {
+            Slot::Occupied { entry, .. } => Some(entry),
+            Slot::Vacant => unreachable!(),
+        }
+    }
+}
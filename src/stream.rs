@@ -0,0 +1,86 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::Context;
+use std::task::Poll;
+use std::time::Instant;
+
+use event_listener::EventListener;
+use futures_core::Stream;
+
+use crate::Sleep;
+use crate::TimedQueue;
+
+/// A `Stream` of the items of a `TimedQueue`, yielding each one as it becomes due.
+///
+/// Created by `TimedQueue::stream` or `TimedQueue::into_stream`. The stream ends when `dequeue` would return
+/// `None`, i.e. once the queue is closed (see `CloseMode`).
+pub struct TimedQueueStream<T> {
+    queue: TimedQueue<T>,
+    /// Completes when something is enqueued or rescheduled. Only held while the stream is pending, so that a
+    /// stream nobody is polling does not take notifications away from other consumers.
+    notified: Option<EventListener>,
+    /// Fires at the expiration of the head of the queue (which is also kept, as the clock may not be real time).
+    /// Only recreated when that changes.
+    sleep: Option<(Instant, Sleep)>,
+}
+
+impl<T> TimedQueueStream<T> {
+    pub(crate) fn new(queue: TimedQueue<T>) -> Self {
+        Self {
+            queue,
+            notified: None,
+            sleep: None,
+        }
+    }
+}
+
+impl<T> Stream for TimedQueueStream<T> {
+    type Item = (T, Option<Instant>);
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            // Registered before looking at the queue, so that a `close` in between is not missed.
+            let inner = &this.queue.inner;
+            let notified = this.notified.get_or_insert_with(|| inner.notify.listen());
+            let woken = Pin::new(notified).poll(cx).is_ready();
+            if woken {
+                this.notified = None;
+            }
+
+            let now = inner.clock.now();
+            let result = inner.with_state(|state| match state.peek_inner(now) {
+                Err(_) if state.is_finished() => None,
+                result => Some(result),
+            });
+            let duration = match result {
+                Some(Ok(item)) => {
+                    this.notified = None;
+                    return Poll::Ready(Some(item));
+                }
+                Some(Err(duration)) => duration,
+                None => {
+                    this.notified = None;
+                    return Poll::Ready(None);
+                }
+            };
+            if woken {
+                continue;
+            }
+
+            if let Some(duration) = duration {
+                let expiration = now + duration;
+                if let Some(sleep_duration) = inner.clock.sleep_duration(duration) {
+                    if !matches!(&this.sleep, Some((armed, _)) if *armed == expiration) {
+                        this.sleep = Some((expiration, inner.timer.sleep(sleep_duration)));
+                    }
+                    if this.sleep.as_mut().unwrap().1.as_mut().poll(cx).is_ready() {
+                        this.sleep = None;
+                        continue;
+                    }
+                }
+            }
+            return Poll::Pending;
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+use std::task::Context;
+use std::task::Poll;
+use std::task::Waker;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::clock::add_saturating;
+
+/// A future returned by `Timer::sleep`.
+pub type Sleep = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Puts async consumers of a `TimedQueue` to sleep, so that the queue does not depend on any one runtime.
+///
+/// Implementations are provided for tokio, async-std and smol (behind the cargo features of the same names),
+/// along with `ThreadTimer`, which needs no runtime at all.
+pub trait Timer: Send + Sync + 'static {
+    /// Returns a future that completes once `duration` of real time has passed.
+    fn sleep(&self, duration: Duration) -> Sleep;
+}
+
+/// The timer used by queues that are not given one: the first of `TokioTimer`, `AsyncStdTimer` and `SmolTimer`
+/// whose feature is enabled, or `ThreadTimer`.
+#[cfg(feature = "tokio")]
+pub(crate) type DefaultTimer = TokioTimer;
+#[cfg(all(not(feature = "tokio"), feature = "async-std"))]
+pub(crate) type DefaultTimer = AsyncStdTimer;
+#[cfg(all(not(feature = "tokio"), not(feature = "async-std"), feature = "smol"))]
+pub(crate) type DefaultTimer = SmolTimer;
+#[cfg(not(any(feature = "tokio", feature = "async-std", feature = "smol")))]
+pub(crate) type DefaultTimer = ThreadTimer;
+
+/// `tokio::time::sleep`. Requires a tokio runtime with the time driver enabled.
+#[cfg(feature = "tokio")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TokioTimer;
+
+#[cfg(feature = "tokio")]
+impl Timer for TokioTimer {
+    fn sleep(&self, duration: Duration) -> Sleep {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// `async_std::task::sleep`.
+#[cfg(feature = "async-std")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AsyncStdTimer;
+
+#[cfg(feature = "async-std")]
+impl Timer for AsyncStdTimer {
+    fn sleep(&self, duration: Duration) -> Sleep {
+        Box::pin(async_std::task::sleep(duration))
+    }
+}
+
+/// `async_io::Timer`, as used by smol.
+#[cfg(feature = "smol")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SmolTimer;
+
+#[cfg(feature = "smol")]
+impl Timer for SmolTimer {
+    fn sleep(&self, duration: Duration) -> Sleep {
+        Box::pin(async move {
+            async_io::Timer::after(duration).await;
+        })
+    }
+}
+
+/// A timer that works under any executor (or `futures::executor::block_on`), by waking sleepers from a
+/// background thread. The thread is shared by every `ThreadTimer`, and started the first time one is used.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ThreadTimer;
+
+impl Timer for ThreadTimer {
+    fn sleep(&self, duration: Duration) -> Sleep {
+        Box::pin(ThreadSleep {
+            deadline: add_saturating(Instant::now(), duration),
+            waker: None,
+        })
+    }
+}
+
+/// Sleepers waiting on the `ThreadTimer` thread, which wakes each one at its deadline.
+struct TimerThread {
+    sleepers: Mutex<Sleepers>,
+    condvar: Condvar,
+}
+
+struct Sleepers {
+    heap: BinaryHeap<Sleeper>,
+    /// Size at which to drop sleepers whose `ThreadSleep` is gone, rather than keep them until their deadline.
+    compact_at: usize,
+}
+
+/// A sleeper is woken through the `Waker` last registered by its `ThreadSleep`, which is shared so that it can
+/// be updated without touching the heap.
+struct Sleeper {
+    deadline: Reverse<Instant>,
+    waker: Arc<Mutex<Option<Waker>>>,
+}
+
+impl PartialEq for Sleeper {
+    fn eq(&self, other: &Self) -> bool {
+        self.deadline == other.deadline
+    }
+}
+
+impl Eq for Sleeper {}
+
+impl PartialOrd for Sleeper {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Sleeper {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.deadline.cmp(&other.deadline)
+    }
+}
+
+impl TimerThread {
+    fn get() -> &'static TimerThread {
+        static THREAD: OnceLock<TimerThread> = OnceLock::new();
+        THREAD.get_or_init(|| {
+            std::thread::Builder::new()
+                .name("timed-queue-timer".into())
+                .spawn(|| TimerThread::get().run())
+                .expect("failed to spawn timer thread");
+            TimerThread {
+                sleepers: Mutex::new(Sleepers {
+                    heap: BinaryHeap::new(),
+                    compact_at: 32,
+                }),
+                condvar: Condvar::new(),
+            }
+        })
+    }
+
+    fn run(&self) {
+        let mut sleepers = self.sleepers.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            while matches!(sleepers.heap.peek(), Some(sleeper) if sleeper.deadline.0 <= now) {
+                let sleeper = sleepers.heap.pop().unwrap();
+                let waker = sleeper.waker.lock().unwrap().take();
+                if let Some(waker) = waker {
+                    waker.wake();
+                }
+            }
+            sleepers = match sleepers.heap.peek() {
+                Some(sleeper) => {
+                    let wait = sleeper.deadline.0 - now;
+                    self.condvar.wait_timeout(sleepers, wait).unwrap().0
+                }
+                None => self.condvar.wait(sleepers).unwrap(),
+            };
+        }
+    }
+
+    fn register(&self, deadline: Instant, waker: Arc<Mutex<Option<Waker>>>) {
+        let mut sleepers = self.sleepers.lock().unwrap();
+        // The thread only needs waking if it is sleeping past the new deadline.
+        let wake = !matches!(sleepers.heap.peek(), Some(sleeper) if sleeper.deadline.0 <= deadline);
+        sleepers.heap.push(Sleeper {
+            deadline: Reverse(deadline),
+            waker,
+        });
+        if sleepers.heap.len() > sleepers.compact_at {
+            sleepers
+                .heap
+                .retain(|sleeper| Arc::strong_count(&sleeper.waker) > 1);
+            sleepers.compact_at = 2 * sleepers.heap.len() + 32;
+        }
+        if wake {
+            self.condvar.notify_one();
+        }
+    }
+}
+
+struct ThreadSleep {
+    deadline: Instant,
+    /// Shared with the timer thread once registered. Cleared on drop, so that the thread does not wake a task
+    /// that no longer sleeps.
+    waker: Option<Arc<Mutex<Option<Waker>>>>,
+}
+
+impl Future for ThreadSleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        if Instant::now() >= this.deadline {
+            return Poll::Ready(());
+        }
+        match &this.waker {
+            Some(waker) => *waker.lock().unwrap() = Some(cx.waker().clone()),
+            None => {
+                let waker = Arc::new(Mutex::new(Some(cx.waker().clone())));
+                TimerThread::get().register(this.deadline, waker.clone());
+                this.waker = Some(waker);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for ThreadSleep {
+    fn drop(&mut self) {
+        if let Some(waker) = &self.waker {
+            waker.lock().unwrap().take();
+        }
+    }
+}
+
+/// Waits for `a` or `b`, whichever completes first.
+pub(crate) async fn race<A, B>(mut a: A, mut b: B)
+where
+    A: Future + Unpin,
+    B: Future + Unpin,
+{
+    std::future::poll_fn(|cx| {
+        if Pin::new(&mut a).poll(cx).is_ready() || Pin::new(&mut b).poll(cx).is_ready() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    })
+    .await
+}
@@ -0,0 +1,360 @@
+use alloc::collections::BinaryHeap;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+
+use crate::heap::Entry;
+use crate::heap::ReadyItem;
+use crate::slab::Slab;
+use crate::Key;
+
+const SLOT_BITS: u32 = 6;
+const SLOTS: usize = 1 << SLOT_BITS;
+/// Enough levels to cover every `u64` tick.
+const LEVELS: usize = 11;
+
+/// Like `TimedHeap`, but keeps items that are not yet due in a hierarchical timing wheel, so that pushing,
+/// rescheduling and removing them takes constant time however many there are.
+///
+/// Expirations are `u64` ticks, of whatever resolution the caller chooses; ticks before the first `now`
+/// passed in are simply due. Items are returned in the same order as from a `TimedHeap`. Finding the next
+/// expiration with `peek` may take time proportional to the number of items expiring around then, so waiting
+/// for it is best left to whatever polls `pop_due` once per tick.
+pub struct TimingWheel<T> {
+    levels: Vec<Level>,
+    /// Items that are due, waiting to be returned in priority order.
+    ready: BinaryHeap<ReadyItem<u64>>,
+    entries: Slab<Entry<T, u64>>,
+    /// Every item due at this tick or earlier has been moved to `ready`.
+    elapsed: u64,
+    next_seq: u64,
+    /// Number of entries in the slots of `levels`, including stale ones.
+    scheduled: usize,
+}
+
+/// Level `n` of the wheel has 64 slots, each holding the items due within a span of `64^n` ticks.
+///
+/// The slots of a level never lag behind `elapsed`: a slot is emptied (and its items moved to a lower level,
+/// or to `ready`) as soon as `elapsed` reaches its start.
+struct Level {
+    /// Bit `i` is set if `slots[i]` is non-empty.
+    occupied: u64,
+    slots: [Vec<Scheduled>; SLOTS],
+}
+
+/// An item's place in a slot. Stale, like a heap entry, once its `seq` no longer matches the item's.
+struct Scheduled {
+    key: Key,
+    seq: u64,
+}
+
+impl<T> Default for TimingWheel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> TimingWheel<T> {
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    /// Creates an empty wheel with room for `capacity` items before reallocating. Only the items themselves are
+    /// preallocated, not the slots of the wheel.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            levels: (0..LEVELS)
+                .map(|_| Level {
+                    occupied: 0,
+                    slots: core::array::from_fn(|_| Vec::new()),
+                })
+                .collect(),
+            ready: BinaryHeap::new(),
+            entries: Slab::with_capacity(capacity),
+            elapsed: 0,
+            next_seq: 0,
+            scheduled: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.len() == 0
+    }
+
+    /// Adds `t`, to be popped no earlier than `expiration` (or as soon as possible if `None`).
+    pub fn push(&mut self, t: T, expiration: Option<u64>) -> Key {
+        self.push_with_priority(t, expiration, 0)
+    }
+
+    /// Like `push`, but among items that are due, those with a higher `priority` are popped first.
+    pub fn push_with_priority(&mut self, t: T, expiration: Option<u64>, priority: u32) -> Key {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let key = self
+            .entries
+            .insert(seq, Entry::new(t, expiration, priority, seq));
+        self.schedule(key, seq, expiration, priority);
+        key
+    }
+
+    /// Returns whether the item is still in the wheel.
+    pub fn contains(&self, key: Key) -> bool {
+        self.entries.get(key).is_some()
+    }
+
+    /// Returns the item, if it is still in the wheel.
+    pub fn get(&self, key: Key) -> Option<&T> {
+        self.entries.get(key).map(|entry| &entry.inner)
+    }
+
+    /// Returns the item, if it is still in the wheel.
+    pub fn get_mut(&mut self, key: Key) -> Option<&mut T> {
+        self.entries.get_mut(key).map(|entry| &mut entry.inner)
+    }
+
+    /// Removes the item, returning it if it had not been popped or removed already.
+    pub fn remove(&mut self, key: Key) -> Option<T> {
+        let entry = self.entries.remove(key)?;
+        self.compact();
+        Some(entry.inner)
+    }
+
+    /// Moves the item to a new expiration, earlier or later. Returns `false` if it is no longer in the wheel.
+    pub fn reschedule(&mut self, key: Key, expiration: Option<u64>) -> bool {
+        let seq = self.next_seq;
+        let priority = match self.entries.get_mut(key) {
+            Some(entry) => {
+                entry.expiration = expiration;
+                entry.seq = seq;
+                entry.priority
+            }
+            None => return false,
+        };
+        self.next_seq += 1;
+        self.schedule(key, seq, expiration, priority);
+        self.compact();
+        true
+    }
+
+    /// Returns the expiration of the item that would be popped next, if any, discarding removed items on the way.
+    pub fn peek(&mut self, now: u64) -> Option<Option<u64>> {
+        self.advance(now);
+        while let Some(item) = self.ready.peek() {
+            if self.is_live(item.key, item.seq.0) {
+                return Some(item.expiration.0);
+            }
+            self.ready.pop();
+        }
+        // The next item is in the first occupied slot, but not necessarily at its start.
+        while let Some((level, slot, _)) = self.next_slot() {
+            let entries = &self.entries;
+            let next = self.levels[level].slots[slot]
+                .iter()
+                .filter_map(|scheduled| {
+                    entries
+                        .get(scheduled.key)
+                        .filter(|entry| entry.seq == scheduled.seq)
+                })
+                .filter_map(|entry| entry.expiration)
+                .min();
+            if next.is_some() {
+                return Some(next);
+            }
+            self.clear_slot(level, slot);
+        }
+        None
+    }
+
+    /// Removes and returns the next item if it is due at `now`, along with its expiration.
+    pub fn pop_due(&mut self, now: u64) -> Option<(T, Option<u64>)> {
+        self.advance(now);
+        while let Some(ReadyItem { seq, key, .. }) = self.ready.pop() {
+            if self.is_live(key, seq.0) {
+                let Entry {
+                    inner, expiration, ..
+                } = self.entries.remove(key).unwrap();
+                return Some((inner, expiration));
+            }
+        }
+        None
+    }
+
+    /// A tick no later than the next expiration of the items that are not yet due, found in constant time.
+    #[cfg(feature = "std")]
+    pub(crate) fn next_wakeup(&self) -> Option<u64> {
+        self.next_slot().map(|(_, _, start)| start)
+    }
+
+    /// Returns the priority and expiration of the item `pop_due(now)` would return, if any.
+    #[cfg(feature = "std")]
+    pub(crate) fn peek_due(&mut self, now: u64) -> Option<(u32, Option<u64>)> {
+        let key = self.due_key(now)?;
+        let entry = self.entries.get(key).unwrap();
+        Some((entry.priority, entry.expiration))
+    }
+
+    /// Returns the key of the item `pop_due(now)` would return, if any.
+    #[cfg(feature = "std")]
+    pub(crate) fn due_key(&mut self, now: u64) -> Option<Key> {
+        self.advance(now);
+        while let Some(item) = self.ready.peek() {
+            if self.is_live(item.key, item.seq.0) {
+                return Some(item.key);
+            }
+            self.ready.pop();
+        }
+        None
+    }
+
+    /// Identifies the item's current schedule: it changes whenever the item is rescheduled.
+    #[cfg(feature = "std")]
+    pub(crate) fn seq(&self, key: Key) -> Option<u64> {
+        self.entries.get(key).map(|entry| entry.seq)
+    }
+
+    /// Whether `key` is in the first occupied slot (or already due), i.e. whoever is waiting for the next
+    /// expiration should wait for this one now.
+    #[cfg(feature = "std")]
+    pub(crate) fn is_pending_head(&self, key: Key) -> bool {
+        match self.entries.get(key).and_then(|entry| entry.expiration) {
+            Some(when) if when > self.elapsed => {
+                let level = level_for(self.elapsed, when);
+                matches!(self.next_slot(), Some((next_level, slot, _))
+                    if next_level == level && slot == slot_for(when, level))
+            }
+            _ => true,
+        }
+    }
+
+    /// The tick up to which items have been moved to `ready`.
+    #[cfg(feature = "std")]
+    pub(crate) fn elapsed(&self) -> u64 {
+        self.elapsed
+    }
+
+    /// Moves `elapsed` back to `now`, after the caller's clock went backwards, and schedules every item again at
+    /// the tick `tick` returns for it, so that none is due before then. Takes time linear in the number of items.
+    #[cfg(feature = "std")]
+    pub(crate) fn rewind(&mut self, now: u64, mut tick: impl FnMut(&T) -> Option<u64>) {
+        for level in &mut self.levels {
+            level.slots.iter_mut().for_each(Vec::clear);
+            level.occupied = 0;
+        }
+        self.ready.clear();
+        self.scheduled = 0;
+        self.elapsed = now;
+        let mut items = Vec::with_capacity(self.entries.len());
+        for (key, entry) in self.entries.iter_mut() {
+            entry.expiration = tick(&entry.inner);
+            items.push((key, entry.seq, entry.expiration, entry.priority));
+        }
+        for (key, seq, expiration, priority) in items {
+            self.schedule(key, seq, expiration, priority);
+        }
+    }
+
+    fn is_live(&self, key: Key, seq: u64) -> bool {
+        matches!(self.entries.get(key), Some(entry) if entry.seq == seq)
+    }
+
+    /// Places `key` in the slot for `expiration`, or in `ready` if it is already due.
+    fn schedule(&mut self, key: Key, seq: u64, expiration: Option<u64>, priority: u32) {
+        match expiration {
+            Some(when) if when > self.elapsed => {
+                let level = level_for(self.elapsed, when);
+                let slot = slot_for(when, level);
+                let level = &mut self.levels[level];
+                level.slots[slot].push(Scheduled { key, seq });
+                level.occupied |= 1 << slot;
+                self.scheduled += 1;
+            }
+            _ => self.ready.push(ReadyItem {
+                priority,
+                expiration: Reverse(expiration),
+                seq: Reverse(seq),
+                key,
+            }),
+        }
+    }
+
+    /// Returns the level and index of the earliest occupied slot, along with the tick at which it starts.
+    fn next_slot(&self) -> Option<(usize, usize, u64)> {
+        // A slot on a lower level always starts before any occupied slot on a higher one.
+        self.levels.iter().enumerate().find_map(|(index, level)| {
+            if level.occupied == 0 {
+                return None;
+            }
+            let shift = SLOT_BITS * index as u32;
+            let current = (self.elapsed >> shift) as usize % SLOTS;
+            let slot = current + (level.occupied >> current).trailing_zeros() as usize;
+            let level_mask = 1u64
+                .checked_shl(shift + SLOT_BITS)
+                .map_or(u64::MAX, |range| range - 1);
+            let start = (self.elapsed & !level_mask) + ((slot as u64) << shift);
+            Some((index, slot, start))
+        })
+    }
+
+    /// Moves `elapsed` up to `now`, emptying every slot it passes: items from level 0 are due, and go to `ready`;
+    /// items from higher levels are rescheduled relative to the new `elapsed`, which puts them on a lower level.
+    fn advance(&mut self, now: u64) {
+        while let Some((level, slot, start)) = self.next_slot() {
+            if start > now {
+                break;
+            }
+            self.elapsed = start;
+            let mut items = core::mem::take(&mut self.levels[level].slots[slot]);
+            self.levels[level].occupied &= !(1 << slot);
+            self.scheduled -= items.len();
+            for Scheduled { key, seq } in items.drain(..) {
+                if let Some(entry) = self.entries.get(key).filter(|entry| entry.seq == seq) {
+                    let (expiration, priority) = (entry.expiration, entry.priority);
+                    self.schedule(key, seq, expiration, priority);
+                }
+            }
+            // Keep the allocation; nothing is rescheduled into the slot being emptied.
+            self.levels[level].slots[slot] = items;
+        }
+        self.elapsed = self.elapsed.max(now);
+    }
+
+    fn clear_slot(&mut self, level: usize, slot: usize) {
+        let level = &mut self.levels[level];
+        self.scheduled -= level.slots[slot].len();
+        level.slots[slot].clear();
+        level.occupied &= !(1 << slot);
+    }
+
+    /// Drops stale entries, once they make up most of the slots.
+    fn compact(&mut self) {
+        if self.scheduled + self.ready.len() > 2 * self.entries.len() + 32 {
+            let entries = &self.entries;
+            let is_live = |key, seq| matches!(entries.get(key), Some(entry) if entry.seq == seq);
+            self.scheduled = 0;
+            for level in &mut self.levels {
+                for (index, slot) in level.slots.iter_mut().enumerate() {
+                    slot.retain(|scheduled| is_live(scheduled.key, scheduled.seq));
+                    if slot.is_empty() {
+                        level.occupied &= !(1 << index);
+                    }
+                    self.scheduled += slot.len();
+                }
+            }
+            self.ready.retain(|item| is_live(item.key, item.seq.0));
+        }
+    }
+}
+
+/// The level on which an item due at `when` is placed: the lowest one on which `when` and `elapsed` are less
+/// than a full turn of the level apart, so that the item's slot is still ahead of `elapsed`.
+fn level_for(elapsed: u64, when: u64) -> usize {
+    let significant = 63 - ((elapsed ^ when) | (SLOTS as u64 - 1)).leading_zeros();
+    (significant / SLOT_BITS) as usize
+}
+
+fn slot_for(when: u64, level: usize) -> usize {
+    (when >> (SLOT_BITS * level as u32)) as usize % SLOTS
+}
@@ -0,0 +1,70 @@
+#![cfg(feature = "std")]
+
+use std::rc::Rc;
+use std::time::Duration;
+use std::time::Instant;
+
+use timed_queue::BuildError;
+use timed_queue::TimedQueue;
+
+#[test]
+fn zero_capacity_limit_is_rejected() {
+    let built = TimedQueue::<u32>::builder().capacity_limit(0).build();
+    assert_eq!(built.err(), Some(BuildError::ZeroCapacityLimit));
+}
+
+#[test]
+fn capacity_over_limit_is_rejected() {
+    let built = TimedQueue::<u32>::builder()
+        .with_capacity(4)
+        .capacity_limit(3)
+        .build();
+    assert_eq!(built.err(), Some(BuildError::CapacityOverLimit));
+
+    let built = TimedQueue::<u32>::builder()
+        .with_capacity(3)
+        .capacity_limit(3)
+        .build();
+    assert!(built.is_ok());
+}
+
+#[test]
+fn too_many_items_are_rejected() {
+    let now = Instant::now();
+    let built = TimedQueue::builder()
+        .capacity_limit(2)
+        .items((0..3).map(|i| (i, Some(now))))
+        .build();
+    assert_eq!(built.err(), Some(BuildError::TooManyItems));
+}
+
+#[test]
+fn zero_tick_is_rejected() {
+    let built = TimedQueue::<u32>::builder()
+        .timing_wheel(Duration::from_secs(0))
+        .build();
+    assert_eq!(built.err(), Some(BuildError::ZeroTick));
+}
+
+#[test]
+fn timing_wheel_can_be_preallocated() {
+    let q = TimedQueue::builder()
+        .timing_wheel(Duration::from_millis(1))
+        .with_capacity(16)
+        .build()
+        .unwrap();
+    q.enqueue(1, None).unwrap();
+    assert_eq!(q.try_dequeue(), Some((1, None)));
+}
+
+#[test]
+fn builder_accepts_items_that_are_not_send() {
+    // Only a custom clock, which keeps a waker for the queue, needs `Send` items.
+    let q = TimedQueue::builder()
+        .with_capacity(4)
+        .timing_wheel(Duration::from_millis(1))
+        .build()
+        .unwrap();
+    q.enqueue(Rc::new(1), None).unwrap();
+    assert_eq!(q.try_dequeue().map(|(t, _)| *t), Some(1));
+}
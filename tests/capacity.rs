@@ -0,0 +1,72 @@
+#![cfg(feature = "std")]
+
+use std::time::Duration;
+
+use timed_queue::CloseMode;
+use timed_queue::TimedQueue;
+use timed_queue::TryEnqueueError;
+
+#[tokio::test(flavor = "current_thread")]
+async fn enqueue_fails_when_full_instead_of_blocking() {
+    let q = TimedQueue::with_capacity_limit(1);
+    q.enqueue(1, None).unwrap();
+    assert!(matches!(q.enqueue(2, None), Err(TryEnqueueError::Full(2))));
+    assert_eq!(q.dequeue().await.map(|(t, _)| t), Some(1));
+    q.enqueue(3, None).unwrap();
+    q.close(CloseMode::Drain);
+    assert!(matches!(
+        q.enqueue(4, None),
+        Err(TryEnqueueError::Closed(4))
+    ));
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn enqueue_wait_resumes_once_a_consumer_frees_space() {
+    let q = TimedQueue::with_capacity_limit(1);
+    q.enqueue(1, None).unwrap();
+    let consumer = {
+        let q = q.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            q.dequeue().await
+        })
+    };
+    q.enqueue_wait(2, None).await.unwrap();
+    assert_eq!(consumer.await.unwrap().map(|(t, _)| t), Some(1));
+    assert_eq!(q.try_dequeue().map(|(t, _)| t), Some(2));
+}
+
+#[test]
+fn enqueue_blocking_waits_for_space() {
+    let q = TimedQueue::with_capacity_limit(1);
+    q.enqueue(1, None).unwrap();
+    let consumer = {
+        let q = q.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            q.dequeue_blocking()
+        })
+    };
+    q.enqueue_blocking(2, None).unwrap();
+    assert_eq!(consumer.join().unwrap().map(|(t, _)| t), Some(1));
+    assert_eq!(q.try_dequeue().map(|(t, _)| t), Some(2));
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn enqueue_many_returns_what_does_not_fit() {
+    let q = TimedQueue::with_capacity_limit(3);
+    q.enqueue(0, None).unwrap();
+    match q.enqueue_many((1..5).map(|t| (t, None))) {
+        Err(TryEnqueueError::Full(rest)) => {
+            assert_eq!(rest.into_iter().map(|(t, _)| t).collect::<Vec<_>>(), [3, 4])
+        }
+        _ => panic!("expected the last two items back"),
+    }
+    assert_eq!(
+        q.drain_due()
+            .into_iter()
+            .map(|(t, _)| t)
+            .collect::<Vec<_>>(),
+        [0, 1, 2]
+    );
+}
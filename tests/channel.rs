@@ -0,0 +1,46 @@
+#![cfg(feature = "std")]
+
+use std::time::Duration;
+use std::time::Instant;
+
+use timed_queue::channel;
+
+#[tokio::test]
+async fn dropping_every_sender_drains_the_queue() {
+    let (sender, receiver) = channel();
+    let senders = vec![sender.clone(), sender.clone(), sender];
+    let expiration = Instant::now() + Duration::from_millis(50);
+    senders[1].enqueue(1, Some(expiration)).unwrap();
+    let other = receiver.clone();
+
+    let mut senders = senders;
+    while senders.len() > 1 {
+        senders.pop();
+        assert!(!receiver.is_closed());
+    }
+    drop(senders);
+    assert!(receiver.is_closed());
+
+    // The pending item is still delivered once due, then both receivers see the end of the queue.
+    let item = tokio::time::timeout(Duration::from_secs(5), receiver.dequeue())
+        .await
+        .expect("pending item was not delivered");
+    assert_eq!(item, Some((1, Some(expiration))));
+    assert_eq!(receiver.dequeue().await, None);
+    assert_eq!(other.dequeue().await, None);
+}
+
+#[tokio::test]
+async fn dropping_the_last_sender_wakes_waiting_receivers() {
+    let (sender, receiver) = channel::<u32>();
+    let consumer = tokio::spawn(async move { receiver.dequeue().await });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!consumer.is_finished());
+
+    drop(sender);
+    let item = tokio::time::timeout(Duration::from_secs(5), consumer)
+        .await
+        .expect("receiver was not woken when the last sender was dropped")
+        .unwrap();
+    assert_eq!(item, None);
+}
@@ -0,0 +1,151 @@
+#![cfg(feature = "std")]
+
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use timed_queue::Clock;
+use timed_queue::ManualClock;
+use timed_queue::Sleep;
+use timed_queue::ThreadTimer;
+use timed_queue::TimedQueue;
+use timed_queue::Timer;
+use timed_queue::WallClock;
+
+#[tokio::test]
+async fn advancing_a_manual_clock_wakes_async_consumers() {
+    let clock = ManualClock::new();
+    let q = TimedQueue::with_clock(clock.clone());
+    q.enqueue(1, Some(clock.now() + Duration::from_secs(60)))
+        .unwrap();
+    let consumer = {
+        let q = q.clone();
+        tokio::spawn(async move { q.dequeue().await })
+    };
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!consumer.is_finished());
+
+    clock.advance(Duration::from_secs(60));
+    let item = tokio::time::timeout(Duration::from_secs(5), consumer)
+        .await
+        .expect("dequeue was not woken by the clock")
+        .unwrap();
+    assert_eq!(item.map(|(t, _)| t), Some(1));
+}
+
+#[test]
+fn advancing_a_manual_clock_wakes_blocking_consumers() {
+    let clock = ManualClock::new();
+    let q = TimedQueue::with_clock(clock.clone());
+    q.enqueue(1, Some(clock.now() + Duration::from_secs(60)))
+        .unwrap();
+    let consumer = {
+        let q = q.clone();
+        std::thread::spawn(move || q.dequeue_blocking())
+    };
+    std::thread::sleep(Duration::from_millis(50));
+    assert!(!consumer.is_finished());
+
+    clock.advance(Duration::from_secs(60));
+    // `join` has no timeout; a lost wakeup shows up as the test hanging.
+    assert_eq!(consumer.join().unwrap().map(|(t, _)| t), Some(1));
+}
+
+#[test]
+fn manual_clock_time_only_moves_when_told() {
+    let clock = ManualClock::new();
+    let q = TimedQueue::with_clock(clock.clone());
+    q.enqueue(1, Some(clock.now() + Duration::from_secs(1)))
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(20));
+    assert!(q.try_dequeue().is_none());
+    clock.advance(Duration::from_millis(999));
+    assert!(q.try_dequeue().is_none());
+    clock.advance(Duration::from_millis(1));
+    assert_eq!(q.try_dequeue().map(|(t, _)| t), Some(1));
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test(start_paused = true)]
+async fn default_clock_follows_paused_tokio_time() {
+    let q = TimedQueue::new();
+    let expiration = tokio::time::Instant::now().into_std() + Duration::from_secs(60);
+    q.enqueue(1, Some(expiration)).unwrap();
+    // With time paused, tokio skips ahead to the consumer's timer instead of waiting a minute.
+    let item = tokio::time::timeout(Duration::from_secs(61), q.dequeue())
+        .await
+        .expect("dequeue did not complete in paused time");
+    assert_eq!(item, Some((1, Some(expiration))));
+    assert!(tokio::time::Instant::now().into_std() >= expiration);
+}
+
+#[test]
+fn wall_clock_round_trips_system_times() {
+    let clock = WallClock::new();
+    let now = SystemTime::now();
+    for time in [
+        now + Duration::from_secs(3600),
+        now - Duration::from_secs(3600),
+    ] {
+        let q = TimedQueue::with_clock(clock);
+        q.enqueue_at_system_time(1, time).unwrap();
+        let expiration = q.peek_deadline().unwrap().unwrap();
+        assert_eq!(q.to_system_time(expiration), time);
+    }
+}
+
+#[test]
+fn default_clock_round_trips_system_times_approximately() {
+    let q = TimedQueue::new();
+    let time = SystemTime::now() + Duration::from_secs(3600);
+    q.enqueue_at_system_time(1, time).unwrap();
+    let expiration = q.peek_deadline().unwrap().unwrap();
+    let back = q.to_system_time(expiration);
+    let error = back
+        .duration_since(time)
+        .unwrap_or_else(|error| error.duration());
+    assert!(error < Duration::from_secs(1), "off by {:?}", error);
+}
+
+/// A `ThreadTimer` that records how long each sleep was asked to be.
+#[derive(Clone, Default)]
+struct RecordingTimer(Arc<Mutex<Vec<Duration>>>);
+
+impl Timer for RecordingTimer {
+    fn sleep(&self, duration: Duration) -> Sleep {
+        self.0.lock().unwrap().push(duration);
+        ThreadTimer.sleep(duration)
+    }
+}
+
+#[tokio::test]
+async fn wall_clock_caps_sleeps_to_recheck_the_head() {
+    let max_sleep = Duration::from_millis(20);
+    let timer = RecordingTimer::default();
+    let q = TimedQueue::builder()
+        .clock(WallClock::new().with_max_sleep(max_sleep))
+        .timer(timer.clone())
+        .build()
+        .unwrap();
+    q.enqueue_at_system_time(1, SystemTime::now() + Duration::from_secs(3600))
+        .unwrap();
+
+    // The consumer wakes up every `max_sleep` to look at the head again, rather than sleeping for an hour
+    // that a step of the system time could make much shorter or longer.
+    let item = q.dequeue_timeout(Duration::from_millis(200)).await;
+    assert_eq!(item, None);
+    let sleeps = timer.0.lock().unwrap().clone();
+    assert!(sleeps.len() >= 5, "only slept {} times", sleeps.len());
+    assert!(
+        sleeps.iter().all(|sleep| *sleep <= max_sleep),
+        "{:?}",
+        sleeps
+    );
+    assert_eq!(
+        WallClock::new()
+            .with_max_sleep(max_sleep)
+            .sleep_duration(Duration::from_secs(3600)),
+        Some(max_sleep)
+    );
+}
@@ -0,0 +1,96 @@
+#![cfg(feature = "std")]
+
+use std::sync::mpsc;
+use std::time::Duration;
+
+use timed_queue::Clock;
+use timed_queue::CloseMode;
+use timed_queue::ManualClock;
+use timed_queue::TimedQueue;
+use timed_queue::TryEnqueueError;
+
+fn queue() -> (ManualClock, TimedQueue<u32>) {
+    let clock = ManualClock::new();
+    let queue = TimedQueue::with_clock(clock.clone());
+    (clock, queue)
+}
+
+#[tokio::test]
+async fn drain_returns_every_remaining_item_then_none() {
+    let (clock, q) = queue();
+    q.enqueue(1, Some(clock.now())).unwrap();
+    q.enqueue(2, Some(clock.now() + Duration::from_secs(10)))
+        .unwrap();
+    q.close(CloseMode::Drain);
+    assert!(q.is_closed());
+    assert!(matches!(
+        q.enqueue(3, None),
+        Err(TryEnqueueError::Closed(3))
+    ));
+
+    assert_eq!(q.dequeue().await.map(|(t, _)| t), Some(1));
+    let consumer = {
+        let q = q.clone();
+        tokio::spawn(async move { q.dequeue().await })
+    };
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!consumer.is_finished());
+    clock.advance(Duration::from_secs(10));
+    assert_eq!(consumer.await.unwrap().map(|(t, _)| t), Some(2));
+    assert_eq!(q.dequeue().await, None);
+}
+
+#[tokio::test]
+async fn immediate_returns_due_items_then_none() {
+    let (clock, q) = queue();
+    let later = clock.now() + Duration::from_secs(10);
+    q.enqueue(1, Some(clock.now())).unwrap();
+    q.enqueue(2, Some(later)).unwrap();
+    q.close(CloseMode::Immediate);
+
+    assert_eq!(q.dequeue().await.map(|(t, _)| t), Some(1));
+    assert_eq!(q.dequeue().await, None);
+    // Not yet due, so still queued.
+    assert_eq!(q.peek_deadline(), Some(Some(later)));
+    clock.advance(Duration::from_secs(10));
+    assert_eq!(q.dequeue().await, Some((2, Some(later))));
+}
+
+#[tokio::test]
+async fn switching_from_drain_to_immediate_sticks() {
+    let (clock, q) = queue();
+    q.enqueue(1, Some(clock.now() + Duration::from_secs(10)))
+        .unwrap();
+    q.close(CloseMode::Drain);
+    q.close(CloseMode::Immediate);
+    q.close(CloseMode::Drain);
+    assert_eq!(q.dequeue().await, None);
+}
+
+#[tokio::test]
+async fn close_wakes_async_and_blocking_consumers() {
+    let (_, q) = queue();
+    let consumer = {
+        let q = q.clone();
+        tokio::spawn(async move { q.dequeue().await })
+    };
+    let (sender, receiver) = mpsc::channel();
+    {
+        let q = q.clone();
+        std::thread::spawn(move || sender.send(q.dequeue_blocking()).unwrap());
+    }
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!consumer.is_finished());
+    assert!(receiver.try_recv().is_err());
+
+    q.close(CloseMode::Drain);
+    let item = tokio::time::timeout(Duration::from_secs(5), consumer)
+        .await
+        .expect("async consumer was not woken by close")
+        .unwrap();
+    assert_eq!(item, None);
+    let item = receiver
+        .recv_timeout(Duration::from_secs(5))
+        .expect("blocking consumer was not woken by close");
+    assert_eq!(item, None);
+}
@@ -0,0 +1,96 @@
+#![cfg(feature = "std")]
+
+use std::time::Duration;
+
+use timed_queue::Clock;
+use timed_queue::CloseMode;
+use timed_queue::ManualClock;
+use timed_queue::TimedQueue;
+
+fn queue() -> (ManualClock, TimedQueue<u32>) {
+    let clock = ManualClock::new();
+    let queue = TimedQueue::with_clock(clock.clone());
+    (clock, queue)
+}
+
+#[tokio::test]
+async fn expired_lease_is_leased_again() {
+    let (clock, q) = queue();
+    q.enqueue(1, Some(clock.now())).unwrap();
+
+    let (item, first) = q.lease(Duration::from_secs(10)).await.unwrap();
+    assert_eq!(item, 1);
+    // Hidden until the visibility timeout.
+    assert_eq!(
+        q.peek_deadline(),
+        Some(Some(clock.now() + Duration::from_secs(10)))
+    );
+    assert_eq!(q.try_dequeue(), None);
+
+    clock.advance(Duration::from_secs(10));
+    let (item, second) = q.lease(Duration::from_secs(10)).await.unwrap();
+    assert_eq!(item, 1);
+
+    // The first consumer was too slow; only the second lease counts.
+    assert!(!first.ack());
+    assert!(q.peek_deadline().is_some());
+    assert!(second.ack());
+    assert_eq!(q.peek_deadline(), None);
+}
+
+#[tokio::test]
+async fn nack_makes_the_item_due_after_the_delay() {
+    let (clock, q) = queue();
+    q.enqueue(1, Some(clock.now())).unwrap();
+
+    let (_, lease) = q.lease(Duration::from_secs(60)).await.unwrap();
+    assert!(lease.nack(Duration::from_secs(5)));
+    assert_eq!(
+        q.peek_deadline(),
+        Some(Some(clock.now() + Duration::from_secs(5)))
+    );
+
+    clock.advance(Duration::from_secs(4));
+    assert_eq!(q.try_dequeue(), None);
+    clock.advance(Duration::from_secs(1));
+    let (item, lease) = q.lease(Duration::from_secs(60)).await.unwrap();
+    assert_eq!(item, 1);
+    assert!(lease.ack());
+}
+
+#[tokio::test]
+async fn stale_nack_does_nothing() {
+    let (clock, q) = queue();
+    q.enqueue(1, Some(clock.now())).unwrap();
+
+    let (_, first) = q.lease(Duration::from_secs(10)).await.unwrap();
+    clock.advance(Duration::from_secs(10));
+    let (_, _second) = q.lease(Duration::from_secs(10)).await.unwrap();
+    assert!(!first.nack(Duration::from_secs(0)));
+    assert_eq!(
+        q.peek_deadline(),
+        Some(Some(clock.now() + Duration::from_secs(10)))
+    );
+}
+
+#[tokio::test]
+async fn draining_queue_waits_for_unacked_leases() {
+    let (clock, q) = queue();
+    q.enqueue(1, Some(clock.now())).unwrap();
+    let (_, lease) = q.lease(Duration::from_secs(60)).await.unwrap();
+    q.close(CloseMode::Drain);
+
+    let consumer = {
+        let q = q.clone();
+        tokio::spawn(async move { q.dequeue().await })
+    };
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!consumer.is_finished());
+
+    assert!(lease.ack());
+    let item = tokio::time::timeout(Duration::from_secs(5), consumer)
+        .await
+        .expect("consumer was not woken by the last ack")
+        .unwrap();
+    assert_eq!(item, None);
+}
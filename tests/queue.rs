@@ -0,0 +1,413 @@
+#![cfg(feature = "std")]
+
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use timed_queue::Clock;
+use timed_queue::ClockWaker;
+use timed_queue::CloseMode;
+use timed_queue::ManualClock;
+use timed_queue::TimedHeap;
+use timed_queue::TimedQueue;
+use timed_queue::TryEnqueueError;
+
+fn queue() -> (ManualClock, TimedQueue<u32>) {
+    let clock = ManualClock::new();
+    let queue = TimedQueue::with_clock(clock.clone());
+    (clock, queue)
+}
+
+#[test]
+fn cancel_removes_a_pending_item() {
+    let (clock, q) = queue();
+    let handle = q.enqueue(1, Some(clock.now())).unwrap();
+    assert!(handle.is_pending());
+    assert_eq!(handle.cancel(), Some(1));
+    assert!(!handle.is_pending());
+    assert_eq!(q.try_dequeue(), None);
+    // Already gone.
+    assert_eq!(handle.cancel(), None);
+}
+
+#[test]
+fn cancel_after_dequeue_returns_none() {
+    let (clock, q) = queue();
+    let handle = q.enqueue(1, Some(clock.now())).unwrap();
+    assert_eq!(q.try_dequeue().map(|(t, _)| t), Some(1));
+    assert!(!handle.is_pending());
+    assert_eq!(handle.cancel(), None);
+}
+
+#[test]
+fn handle_outliving_its_queue_does_nothing() {
+    let (clock, q) = queue();
+    let handle = q.enqueue(1, Some(clock.now())).unwrap();
+    drop(q);
+    assert!(!handle.is_pending());
+    assert_eq!(handle.cancel(), None);
+    assert!(!handle.reschedule(None));
+}
+
+#[tokio::test]
+async fn rescheduling_earlier_wakes_a_sleeping_consumer() {
+    // Real time, so that the consumer sleeps on a timer toward the old head.
+    let q = TimedQueue::new();
+    let handle = q
+        .enqueue(1, Some(Instant::now() + Duration::from_secs(60)))
+        .unwrap();
+    let consumer = {
+        let q = q.clone();
+        tokio::spawn(async move { q.dequeue().await })
+    };
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!consumer.is_finished());
+
+    let expiration = Instant::now() + Duration::from_millis(50);
+    assert!(q.reschedule(&handle, Some(expiration)));
+    let item = tokio::time::timeout(Duration::from_secs(5), consumer)
+        .await
+        .expect("consumer kept sleeping toward the old expiration")
+        .unwrap();
+    assert_eq!(item, Some((1, Some(expiration))));
+}
+
+#[test]
+fn reschedule_ignores_handles_from_other_queues() {
+    let (clock, q) = queue();
+    let (_, other) = queue();
+    let expiration = clock.now() + Duration::from_secs(60);
+    let handle = other.enqueue(1, Some(expiration)).unwrap();
+    q.enqueue(2, Some(expiration)).unwrap();
+
+    assert!(!q.reschedule(&handle, None));
+    assert_eq!(q.try_dequeue(), None);
+    assert_eq!(other.peek_deadline(), Some(Some(expiration)));
+    assert!(other.reschedule(&handle, None));
+    assert_eq!(other.try_dequeue(), Some((1, None)));
+}
+
+#[tokio::test]
+async fn dequeue_timeout_gives_up() {
+    let (clock, q) = queue();
+    q.enqueue(1, Some(clock.now() + Duration::from_secs(60)))
+        .unwrap();
+    let consumer = {
+        let q = q.clone();
+        tokio::spawn(async move { q.dequeue_timeout(Duration::from_secs(10)).await })
+    };
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!consumer.is_finished());
+
+    clock.advance(Duration::from_secs(10));
+    let item = tokio::time::timeout(Duration::from_secs(5), consumer)
+        .await
+        .expect("consumer was not woken by the clock")
+        .unwrap();
+    assert_eq!(item, None);
+    // Still there for the next consumer.
+    assert_eq!(
+        q.peek_deadline(),
+        Some(Some(clock.now() + Duration::from_secs(50)))
+    );
+}
+
+#[tokio::test]
+async fn dequeue_until_returns_an_item_due_at_the_deadline() {
+    let (clock, q) = queue();
+    let deadline = clock.now() + Duration::from_secs(10);
+    q.enqueue(1, Some(deadline)).unwrap();
+    let consumer = {
+        let q = q.clone();
+        tokio::spawn(async move { q.dequeue_until(deadline).await })
+    };
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!consumer.is_finished());
+
+    clock.advance(Duration::from_secs(10));
+    let item = tokio::time::timeout(Duration::from_secs(5), consumer)
+        .await
+        .expect("consumer was not woken by the clock")
+        .unwrap();
+    assert_eq!(item, Some((1, Some(deadline))));
+
+    // Also when the deadline has already been reached on the first look.
+    q.enqueue(2, Some(deadline)).unwrap();
+    assert_eq!(q.dequeue_until(deadline).await, Some((2, Some(deadline))));
+    assert_eq!(q.dequeue_until(deadline).await, None);
+}
+
+#[tokio::test]
+async fn dequeue_batch_respects_max() {
+    let (clock, q) = queue();
+    for i in 0..5 {
+        q.enqueue(i, Some(clock.now())).unwrap();
+    }
+    let batch: Vec<_> = q
+        .dequeue_batch(3)
+        .await
+        .into_iter()
+        .map(|(t, _)| t)
+        .collect();
+    assert_eq!(batch, [0, 1, 2]);
+
+    // Returns at once, leaving the due items alone.
+    assert_eq!(q.dequeue_batch(0).await, []);
+    let batch: Vec<_> = q
+        .dequeue_batch(10)
+        .await
+        .into_iter()
+        .map(|(t, _)| t)
+        .collect();
+    assert_eq!(batch, [3, 4]);
+}
+
+#[test]
+fn drain_due_takes_every_due_item_and_leaves_the_rest() {
+    let (clock, q) = queue();
+    let later = clock.now() + Duration::from_secs(60);
+    q.enqueue(10, Some(later)).unwrap();
+    for i in 0..3 {
+        q.enqueue(i, Some(clock.now())).unwrap();
+    }
+    q.enqueue(11, Some(later)).unwrap();
+
+    let due: Vec<_> = q.drain_due().into_iter().map(|(t, _)| t).collect();
+    assert_eq!(due, [0, 1, 2]);
+    assert_eq!(q.drain_due(), []);
+    assert_eq!(q.peek_deadline(), Some(Some(later)));
+
+    clock.advance(Duration::from_secs(60));
+    let due: Vec<_> = q.drain_due().into_iter().map(|(t, _)| t).collect();
+    assert_eq!(due, [10, 11]);
+}
+
+/// Deliberately neither `Ord` nor `Eq`: items are only ever ordered by their expiration.
+#[derive(Debug, PartialEq)]
+struct Job(&'static str);
+
+#[test]
+fn equal_expirations_come_out_in_fifo_order() {
+    let clock = ManualClock::new();
+    let heap = TimedQueue::with_clock(clock.clone());
+    let wheel = TimedQueue::builder()
+        .clock(clock.clone())
+        .timing_wheel(Duration::from_millis(1))
+        .build()
+        .unwrap();
+    let names = ["c", "a", "d", "b", "e"];
+    let expiration = clock.now() + Duration::from_secs(1);
+    for q in [&heap, &wheel] {
+        for name in names {
+            q.enqueue(Job(name), Some(expiration)).unwrap();
+        }
+    }
+
+    clock.advance(Duration::from_secs(1));
+    for q in [&heap, &wheel] {
+        let order: Vec<_> = q.drain_due().into_iter().map(|(job, _)| job).collect();
+        assert_eq!(order, names.map(Job));
+    }
+}
+
+#[test]
+fn timed_heap_pops_equal_expirations_in_push_order() {
+    let mut heap = TimedHeap::new();
+    for name in ["c", "a", "b"] {
+        heap.push(Job(name), Some(5u64));
+    }
+    heap.push(Job("first"), Some(4));
+    let order: Vec<_> = std::iter::from_fn(|| heap.pop_due(5).map(|(job, _)| job.0)).collect();
+    assert_eq!(order, ["first", "c", "a", "b"]);
+}
+
+#[test]
+fn priority_only_applies_among_due_items() {
+    let (clock, q) = queue();
+    let now = clock.now();
+    q.enqueue_with_priority(1, Some(now + Duration::from_secs(10)), 9)
+        .unwrap();
+    q.enqueue_with_priority(2, Some(now), 0).unwrap();
+    q.enqueue_with_priority(3, None, 0).unwrap();
+    q.enqueue_with_priority(4, Some(now), 5).unwrap();
+
+    // Item 1 is not due, whatever its priority; `None` is due at once, but after higher priorities.
+    let order: Vec<_> = q.drain_due().into_iter().map(|(t, _)| t).collect();
+    assert_eq!(order, [4, 3, 2]);
+
+    clock.advance(Duration::from_secs(10));
+    q.enqueue_with_priority(5, Some(clock.now()), 0).unwrap();
+    let order: Vec<_> = q.drain_due().into_iter().map(|(t, _)| t).collect();
+    assert_eq!(order, [1, 5]);
+}
+
+/// A `ManualClock` that records how long consumers ask to wait.
+#[derive(Clone)]
+struct RecordingClock {
+    clock: ManualClock,
+    waits: Arc<Mutex<Vec<Duration>>>,
+}
+
+impl Clock for RecordingClock {
+    fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
+    fn sleep_duration(&self, remaining: Duration) -> Option<Duration> {
+        self.waits.lock().unwrap().push(remaining);
+        self.clock.sleep_duration(remaining)
+    }
+
+    fn register(&self, waker: ClockWaker) {
+        self.clock.register(waker)
+    }
+}
+
+/// Returns a queue coalescing expirations within `window`, and the clock it uses.
+fn coalescing_queue(window: Duration) -> (RecordingClock, TimedQueue<u32>) {
+    let clock = RecordingClock {
+        clock: ManualClock::new(),
+        waits: Arc::default(),
+    };
+    let queue = TimedQueue::builder()
+        .clock(clock.clone())
+        .resolution(window)
+        .build()
+        .unwrap();
+    (clock, queue)
+}
+
+async fn first_wait(clock: &RecordingClock) -> Duration {
+    loop {
+        if let Some(wait) = clock.waits.lock().unwrap().first() {
+            return *wait;
+        }
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+}
+
+#[tokio::test]
+async fn expirations_within_a_window_are_coalesced() {
+    let window = Duration::from_millis(10);
+    let (clock, q) = coalescing_queue(window);
+    let start = clock.now();
+    for i in 1..=3 {
+        q.enqueue(i, Some(start + Duration::from_micros(2 * u64::from(i))))
+            .unwrap();
+    }
+    let consumer = {
+        let q = q.clone();
+        tokio::spawn(async move { q.dequeue_batch(10).await })
+    };
+
+    // One wait, until the end of the window, rather than one per expiration.
+    assert_eq!(first_wait(&clock).await, window);
+    clock.clock.advance(window);
+    let batch = tokio::time::timeout(Duration::from_secs(5), consumer)
+        .await
+        .expect("consumer was not woken at the end of the window")
+        .unwrap();
+    assert_eq!(batch.iter().map(|(t, _)| *t).collect::<Vec<_>>(), [1, 2, 3]);
+    assert_eq!(clock.waits.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn coalescing_never_returns_items_early() {
+    let (clock, q) = coalescing_queue(Duration::from_millis(10));
+    let start = clock.now();
+    q.enqueue(1, Some(start + Duration::from_micros(1)))
+        .unwrap();
+    q.enqueue(2, Some(start + Duration::from_micros(5)))
+        .unwrap();
+
+    clock.clock.advance(Duration::from_micros(3));
+    assert_eq!(q.try_dequeue().map(|(t, _)| t), Some(1));
+    assert_eq!(q.try_dequeue(), None);
+    clock.clock.advance(Duration::from_micros(2));
+    assert_eq!(q.try_dequeue().map(|(t, _)| t), Some(2));
+}
+
+#[tokio::test]
+async fn zero_resolution_waits_for_each_expiration() {
+    let (clock, q) = coalescing_queue(Duration::from_secs(0));
+    let start = clock.now();
+    q.enqueue(1, Some(start + Duration::from_micros(2)))
+        .unwrap();
+    q.enqueue(2, Some(start + Duration::from_micros(4)))
+        .unwrap();
+    let consumer = {
+        let q = q.clone();
+        tokio::spawn(async move { q.dequeue().await })
+    };
+
+    assert_eq!(first_wait(&clock).await, Duration::from_micros(2));
+    clock.clock.advance(Duration::from_micros(2));
+    let item = tokio::time::timeout(Duration::from_secs(5), consumer)
+        .await
+        .expect("consumer was not woken at the expiration")
+        .unwrap();
+    assert_eq!(item.map(|(t, _)| t), Some(1));
+}
+
+#[test]
+fn enqueue_many_on_a_closed_queue_returns_every_item() {
+    let (clock, q) = queue();
+    q.close(CloseMode::Drain);
+    let items = vec![(1, Some(clock.now())), (2, None)];
+    match q.enqueue_many(items.clone()) {
+        Err(TryEnqueueError::Closed(rejected)) => assert_eq!(rejected, items),
+        other => panic!("expected Closed, got {:?}", other.map(|_| ())),
+    }
+    assert_eq!(q.peek_deadline(), None);
+}
+
+#[tokio::test]
+async fn enqueue_many_wakes_a_consumer_for_an_earlier_expiration() {
+    // Real time, so that the consumer sleeps on a timer toward the old head.
+    let q = TimedQueue::new();
+    q.enqueue(1, Some(Instant::now() + Duration::from_secs(60)))
+        .unwrap();
+    let consumer = {
+        let q = q.clone();
+        tokio::spawn(async move { q.dequeue().await })
+    };
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let expiration = Instant::now() + Duration::from_millis(50);
+    q.enqueue_many(vec![
+        (2, Some(expiration)),
+        (3, Some(expiration + Duration::from_secs(1))),
+    ])
+    .unwrap();
+    let item = tokio::time::timeout(Duration::from_secs(5), consumer)
+        .await
+        .expect("consumer kept sleeping toward the old head")
+        .unwrap();
+    assert_eq!(item, Some((2, Some(expiration))));
+}
+
+#[tokio::test]
+async fn enqueue_many_wakes_a_consumer_per_due_item() {
+    let (clock, q) = queue();
+    let consumers: Vec<_> = (0..3)
+        .map(|_| {
+            let q = q.clone();
+            tokio::spawn(async move { q.dequeue().await })
+        })
+        .collect();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    q.enqueue_many((1..=3).map(|i| (i, Some(clock.now()))))
+        .unwrap();
+    let mut items = Vec::new();
+    for consumer in consumers {
+        let item = tokio::time::timeout(Duration::from_secs(5), consumer)
+            .await
+            .expect("not every consumer was woken")
+            .unwrap();
+        items.push(item.unwrap().0);
+    }
+    items.sort_unstable();
+    assert_eq!(items, [1, 2, 3]);
+}
@@ -0,0 +1,133 @@
+#![cfg(feature = "std")]
+
+use std::time::Duration;
+
+use timed_queue::Clock;
+use timed_queue::ManualClock;
+use timed_queue::RetryPolicy;
+use timed_queue::TimedQueue;
+
+fn secs(secs: u64) -> Duration {
+    Duration::from_secs(secs)
+}
+
+#[test]
+fn first_attempt_is_never_delayed() {
+    let policies = [
+        RetryPolicy::Fixed(secs(5)),
+        RetryPolicy::Linear {
+            initial: secs(1),
+            increment: secs(2),
+        },
+        RetryPolicy::Exponential {
+            initial: secs(1),
+            max: secs(60),
+        },
+        RetryPolicy::DecorrelatedJitter {
+            base: secs(1),
+            max: secs(60),
+        },
+    ];
+    for policy in &policies {
+        assert_eq!(policy.delay(0, Some(secs(30))), secs(0), "{:?}", policy);
+    }
+}
+
+#[test]
+fn fixed_and_linear_delays() {
+    let fixed = RetryPolicy::Fixed(secs(5));
+    assert_eq!(fixed.delay(1, None), secs(5));
+    assert_eq!(fixed.delay(u32::MAX, None), secs(5));
+
+    let linear = RetryPolicy::Linear {
+        initial: secs(1),
+        increment: secs(2),
+    };
+    assert_eq!(linear.delay(1, None), secs(1));
+    assert_eq!(linear.delay(2, None), secs(3));
+    assert_eq!(linear.delay(3, None), secs(5));
+
+    let huge = RetryPolicy::Linear {
+        initial: secs(1),
+        increment: Duration::MAX,
+    };
+    assert_eq!(huge.delay(3, None), Duration::MAX);
+}
+
+#[test]
+fn exponential_delays_double_up_to_max() {
+    let policy = RetryPolicy::Exponential {
+        initial: secs(1),
+        max: secs(60),
+    };
+    let delays: Vec<_> = (1..=8).map(|attempt| policy.delay(attempt, None)).collect();
+    assert_eq!(
+        delays,
+        [1, 2, 4, 8, 16, 32, 60, 60]
+            .iter()
+            .map(|s| secs(*s))
+            .collect::<Vec<_>>()
+    );
+    // Far past where the factor overflows.
+    assert_eq!(policy.delay(40, None), secs(60));
+    assert_eq!(policy.delay(u32::MAX, None), secs(60));
+
+    let zero = RetryPolicy::Exponential {
+        initial: secs(0),
+        max: secs(60),
+    };
+    assert_eq!(zero.delay(u32::MAX, None), secs(0));
+}
+
+#[test]
+fn decorrelated_jitter_stays_in_bounds() {
+    let base = Duration::from_millis(100);
+    let max = secs(10);
+    let policy = RetryPolicy::DecorrelatedJitter { base, max };
+    let mut previous = None;
+    for attempt in 1..1000 {
+        let delay = policy.delay(attempt, previous);
+        let upper = previous.unwrap_or(base).saturating_mul(3).min(max);
+        assert!(
+            base <= delay && delay <= upper,
+            "{:?} after {:?}",
+            delay,
+            previous
+        );
+        previous = Some(delay);
+    }
+
+    // A previous delay shorter than `base` cannot push the next one below it.
+    assert_eq!(policy.delay(1, Some(Duration::from_millis(10))), base);
+    assert!(policy.delay(1, Some(Duration::MAX)) <= max);
+}
+
+#[tokio::test]
+async fn lease_retry_counts_the_next_attempt() {
+    let clock = ManualClock::new();
+    let q = TimedQueue::builder()
+        .clock(clock.clone())
+        .retry_policy(RetryPolicy::Fixed(secs(5)))
+        .build()
+        .unwrap();
+    q.enqueue_retry("job", 0).unwrap();
+
+    let (first, lease) = q.lease(secs(60)).await.unwrap();
+    assert_eq!((first.item, first.attempt), ("job", 0));
+    assert!(lease.retry());
+    assert_eq!(q.peek_deadline(), Some(Some(clock.now() + secs(5))));
+
+    clock.advance(secs(5));
+    let (second, lease) = q.lease(secs(60)).await.unwrap();
+    assert_eq!((second.item, second.attempt), ("job", 1));
+    assert!(lease.retry());
+
+    clock.advance(secs(5));
+    let (third, stale) = q.lease(secs(60)).await.unwrap();
+    assert_eq!(third.attempt, 2);
+    clock.advance(secs(60));
+    let (_, lease) = q.lease(secs(60)).await.unwrap();
+    // The expired lease can no longer count an attempt.
+    assert!(!stale.retry());
+    assert!(lease.ack());
+}
@@ -0,0 +1,70 @@
+#![cfg(feature = "std")]
+
+//! Consumers sleeping on each `Timer`, outside of tokio.
+
+use std::future::Future;
+use std::pin::pin;
+use std::sync::Arc;
+use std::task::Context;
+use std::task::Poll;
+use std::task::Wake;
+use std::task::Waker;
+use std::thread::Thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use timed_queue::ThreadTimer;
+use timed_queue::TimedQueue;
+use timed_queue::Timer;
+
+/// Wakes the thread blocked in `block_on`.
+struct Unpark(Thread);
+
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// The smallest executor that can run a future: polls it on the current thread, parking in between.
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let waker = Waker::from(Arc::new(Unpark(std::thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => std::thread::park(),
+        }
+    }
+}
+
+/// Dequeues an item due shortly, and waits for a timeout with nothing due, both sleeping on `timer`.
+async fn sleep_on<M: Timer>(timer: M) {
+    let q = TimedQueue::with_timer(timer);
+    let expiration = Instant::now() + Duration::from_millis(50);
+    q.enqueue(1, Some(expiration)).unwrap();
+    assert_eq!(q.dequeue().await, Some((1, Some(expiration))));
+    assert!(Instant::now() >= expiration);
+
+    let start = Instant::now();
+    assert_eq!(q.dequeue_timeout(Duration::from_millis(50)).await, None);
+    assert!(start.elapsed() >= Duration::from_millis(50));
+}
+
+#[test]
+fn thread_timer_without_a_runtime() {
+    block_on(sleep_on(ThreadTimer));
+}
+
+#[cfg(feature = "smol")]
+#[test]
+fn smol_timer() {
+    async_io::block_on(sleep_on(timed_queue::SmolTimer));
+}
+
+#[cfg(feature = "async-std")]
+#[test]
+fn async_std_timer() {
+    async_std::task::block_on(sleep_on(timed_queue::AsyncStdTimer));
+}
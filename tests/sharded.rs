@@ -0,0 +1,124 @@
+#![cfg(feature = "std")]
+
+use std::time::Duration;
+use std::time::Instant;
+
+use timed_queue::Clock;
+use timed_queue::CloseMode;
+use timed_queue::ManualClock;
+use timed_queue::ShardOrder;
+use timed_queue::ShardedTimedQueue;
+
+/// Enqueues `(item, expiration offset in seconds, priority)` from one thread per item, so that the items are
+/// spread over the shards.
+fn enqueue_from_threads(q: &ShardedTimedQueue<u32>, start: Instant, items: &[(u32, u64, u32)]) {
+    std::thread::scope(|scope| {
+        for &(item, offset, priority) in items {
+            scope.spawn(move || {
+                let expiration = start + Duration::from_secs(offset);
+                q.enqueue_with_priority(item, Some(expiration), priority)
+                    .unwrap();
+            });
+        }
+    });
+}
+
+fn drain(q: &ShardedTimedQueue<u32>) -> Vec<u32> {
+    std::iter::from_fn(|| q.try_dequeue().map(|(t, _)| t)).collect()
+}
+
+#[test]
+fn exact_order_returns_the_earliest_item_of_any_shard() {
+    let clock = ManualClock::new();
+    let q = ShardedTimedQueue::with_clock(4, ShardOrder::Exact, clock.clone());
+    let start = clock.now();
+    let items: Vec<_> = (0..16).map(|i| (i, u64::from(16 - i), 0)).collect();
+    enqueue_from_threads(&q, start, &items);
+
+    clock.advance(Duration::from_secs(8));
+    assert_eq!(drain(&q), [15, 14, 13, 12, 11, 10, 9, 8]);
+    clock.advance(Duration::from_secs(8));
+    assert_eq!(drain(&q), [7, 6, 5, 4, 3, 2, 1, 0]);
+}
+
+#[test]
+fn exact_order_applies_priority_across_shards() {
+    let clock = ManualClock::new();
+    let q = ShardedTimedQueue::with_clock(4, ShardOrder::Exact, clock.clone());
+    let start = clock.now();
+    enqueue_from_threads(&q, start, &[(1, 1, 0), (2, 2, 5), (3, 3, 1), (4, 60, 9)]);
+
+    clock.advance(Duration::from_secs(10));
+    // The highest priority among due items, however early the others expired; item 4 is not due yet.
+    assert_eq!(drain(&q), [2, 3, 1]);
+}
+
+#[test]
+fn relaxed_order_returns_every_item() {
+    let clock = ManualClock::new();
+    let q = ShardedTimedQueue::with_clock(4, ShardOrder::Relaxed, clock.clone());
+    let start = clock.now();
+    let items: Vec<_> = (0..32).map(|i| (i, u64::from(i % 5), 0)).collect();
+    enqueue_from_threads(&q, start, &items);
+
+    clock.advance(Duration::from_secs(5));
+    let mut dequeued = drain(&q);
+    dequeued.sort_unstable();
+    assert_eq!(dequeued, (0..32).collect::<Vec<_>>());
+}
+
+#[tokio::test]
+async fn close_ends_dequeue() {
+    let clock = ManualClock::new();
+    let q = ShardedTimedQueue::<u32>::with_clock(4, ShardOrder::Exact, clock.clone());
+    let consumer = {
+        let q = q.clone();
+        tokio::spawn(async move { q.dequeue().await })
+    };
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!consumer.is_finished());
+
+    q.close(CloseMode::Immediate);
+    assert!(q.is_closed());
+    let item = tokio::time::timeout(Duration::from_secs(5), consumer)
+        .await
+        .expect("consumer was not woken by close")
+        .unwrap();
+    assert_eq!(item, None);
+}
+
+#[tokio::test]
+async fn draining_returns_the_rest_then_none() {
+    let clock = ManualClock::new();
+    let q = ShardedTimedQueue::with_clock(2, ShardOrder::Relaxed, clock.clone());
+    enqueue_from_threads(&q, clock.now(), &[(1, 0, 0), (2, 0, 0)]);
+    q.close(CloseMode::Drain);
+
+    let mut dequeued = vec![q.dequeue().await.unwrap().0, q.dequeue().await.unwrap().0];
+    dequeued.sort_unstable();
+    assert_eq!(dequeued, [1, 2]);
+    assert_eq!(q.dequeue().await, None);
+}
+
+#[tokio::test]
+async fn dequeue_timeout_gives_up() {
+    let clock = ManualClock::new();
+    let q = ShardedTimedQueue::with_clock(4, ShardOrder::Exact, clock.clone());
+    q.enqueue(1, Some(clock.now() + Duration::from_secs(60)))
+        .unwrap();
+    let consumer = {
+        let q = q.clone();
+        tokio::spawn(async move { q.dequeue_timeout(Duration::from_secs(10)).await })
+    };
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!consumer.is_finished());
+
+    clock.advance(Duration::from_secs(10));
+    let item = tokio::time::timeout(Duration::from_secs(5), consumer)
+        .await
+        .expect("consumer was not woken by the clock")
+        .unwrap();
+    assert_eq!(item, None);
+    clock.advance(Duration::from_secs(50));
+    assert_eq!(q.try_dequeue().map(|(t, _)| t), Some(1));
+}
@@ -0,0 +1,31 @@
+#![cfg(feature = "std")]
+
+use std::future::poll_fn;
+use std::pin::Pin;
+use std::time::Duration;
+
+use futures_core::Stream;
+use timed_queue::TimedQueue;
+
+#[tokio::test]
+async fn idle_stream_does_not_absorb_wakeups() {
+    let q = TimedQueue::new();
+    let mut stream = q.stream();
+    q.enqueue(0, None).unwrap();
+    let first = poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await;
+    assert_eq!(first.map(|(t, _)| t), Some(0));
+
+    // The stream is now left alone while another consumer waits on the same queue.
+    let waiter = {
+        let q = q.clone();
+        tokio::spawn(async move { q.dequeue().await })
+    };
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    q.enqueue(1, None).unwrap();
+    let second = tokio::time::timeout(Duration::from_millis(500), waiter)
+        .await
+        .expect("dequeue was not woken")
+        .unwrap();
+    assert_eq!(second.map(|(t, _)| t), Some(1));
+    drop(stream);
+}
@@ -0,0 +1,243 @@
+use timed_queue::Key;
+use timed_queue::TimedHeap;
+use timed_queue::TimingWheel;
+
+/// Xorshift, seeded per test so that failures can be reproduced.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Uniform enough in `0..n`, for `n > 0`.
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+}
+
+/// Runs the same random operations on a `TimingWheel` and a `TimedHeap`, with expirations spread over about
+/// `scale` ticks, and checks that they agree after each one.
+fn check_against_heap(seed: u64, scale: u64) {
+    let mut rng = Rng::new(seed);
+    let mut wheel = TimingWheel::new();
+    let mut heap = TimedHeap::<u64, u64>::new();
+    // Keys of both, including those of items already popped or removed.
+    let mut keys: Vec<(Key, Key)> = Vec::new();
+    let mut now = 0u64;
+    let mut next_id = 0u64;
+
+    for step in 0..2000 {
+        let expiration = |rng: &mut Rng, now: u64| match rng.below(8) {
+            0 => None,
+            // Overdue already.
+            1 => Some(now.saturating_sub(rng.below(scale))),
+            _ => Some(now.saturating_add(rng.below(scale))),
+        };
+        match rng.below(10) {
+            0..=2 => {
+                let expiration = expiration(&mut rng, now);
+                let id = next_id;
+                next_id += 1;
+                let keys_pushed = if rng.below(2) == 0 {
+                    (heap.push(id, expiration), wheel.push(id, expiration))
+                } else {
+                    let priority = rng.below(3) as u32;
+                    (
+                        heap.push_with_priority(id, expiration, priority),
+                        wheel.push_with_priority(id, expiration, priority),
+                    )
+                };
+                keys.push(keys_pushed);
+            }
+            3 if !keys.is_empty() => {
+                let (heap_key, wheel_key) = keys[rng.below(keys.len() as u64) as usize];
+                let expiration = expiration(&mut rng, now);
+                assert_eq!(
+                    heap.reschedule(heap_key, expiration),
+                    wheel.reschedule(wheel_key, expiration),
+                    "reschedule at step {}",
+                    step
+                );
+            }
+            4 if !keys.is_empty() => {
+                let (heap_key, wheel_key) = keys[rng.below(keys.len() as u64) as usize];
+                assert_eq!(
+                    heap.remove(heap_key),
+                    wheel.remove(wheel_key),
+                    "remove at step {}",
+                    step
+                );
+            }
+            5..=6 => now = now.saturating_add(rng.below(scale / 4 + 1)),
+            _ => {
+                for _ in 0..rng.below(4) {
+                    assert_eq!(
+                        heap.pop_due(now),
+                        wheel.pop_due(now),
+                        "pop_due at step {}",
+                        step
+                    );
+                }
+            }
+        }
+        assert_eq!(heap.len(), wheel.len(), "len at step {}", step);
+        assert_eq!(heap.peek(now), wheel.peek(now), "peek at step {}", step);
+    }
+
+    // Whatever is left comes out in the same order.
+    loop {
+        let popped = heap.pop_due(u64::MAX);
+        assert_eq!(popped, wheel.pop_due(u64::MAX));
+        if popped.is_none() {
+            break;
+        }
+    }
+    assert!(wheel.is_empty());
+}
+
+#[test]
+fn wheel_matches_heap() {
+    let scales = [
+        10,
+        64,
+        1000,
+        1 << 20,
+        1 << 40,
+        u64::MAX / (1 << 20),
+        u64::MAX / 2,
+    ];
+    for (i, &scale) in scales.iter().enumerate() {
+        for seed in 0..10 {
+            check_against_heap(i as u64 * 100 + seed, scale);
+        }
+    }
+}
+
+/// The timing wheel behind `TimedQueue::with_timing_wheel`.
+#[cfg(feature = "std")]
+mod queue {
+    use std::collections::HashMap;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    use timed_queue::Clock;
+    use timed_queue::ManualClock;
+    use timed_queue::TimedQueue;
+
+    use super::Rng;
+
+    #[test]
+    fn wheel_queue_returns_items_within_one_tick() {
+        let tick = Duration::from_millis(10);
+        let clock = ManualClock::new();
+        let queue = TimedQueue::builder()
+            .clock(clock.clone())
+            .timing_wheel(tick)
+            .build()
+            .unwrap();
+        let mut rng = Rng::new(1);
+        let start = clock.now();
+        let mut pending: HashMap<usize, Instant> = (0..500)
+            .map(|i| (i, start + Duration::from_micros(rng.below(1_000_000))))
+            .collect();
+        for (&i, &expiration) in &pending {
+            queue.enqueue(i, Some(expiration)).unwrap();
+        }
+
+        while !pending.is_empty() {
+            clock.advance(Duration::from_micros(rng.below(3000)));
+            let now = clock.now();
+            while let Some((i, expiration)) = queue.try_dequeue() {
+                assert_eq!(pending.remove(&i), expiration);
+                assert!(expiration.unwrap() <= now, "item {} returned early", i);
+            }
+            for (i, expiration) in &pending {
+                assert!(*expiration + tick > now, "item {} more than a tick late", i);
+            }
+        }
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test(start_paused = true)]
+    async fn with_timing_wheel_never_returns_items_early() {
+        let tick = Duration::from_millis(10);
+        let queue = TimedQueue::with_timing_wheel(tick);
+        let start = tokio::time::Instant::now().into_std();
+        for i in 0..50u64 {
+            queue
+                .enqueue(i, Some(start + Duration::from_millis(i * 7)))
+                .unwrap();
+        }
+        for _ in 0..50 {
+            let (_, expiration) = queue.dequeue().await.unwrap();
+            let expiration = expiration.unwrap();
+            let now = tokio::time::Instant::now().into_std();
+            assert!(expiration <= now);
+            // Tokio's timers themselves round up to the millisecond.
+            assert!(now < expiration + tick + Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn wheel_queue_holds_items_back_after_the_clock_goes_back() {
+        let clock = ManualClock::new();
+        let start = clock.now();
+        let queue = TimedQueue::builder()
+            .clock(clock.clone())
+            .timing_wheel(Duration::from_millis(10))
+            .build()
+            .unwrap();
+        clock.advance(Duration::from_secs(3600));
+        assert_eq!(queue.try_dequeue(), None);
+
+        clock.set(start);
+        queue
+            .enqueue(1, Some(start + Duration::from_secs(600)))
+            .unwrap();
+        queue.enqueue(2, Some(start)).unwrap();
+        assert_eq!(queue.try_dequeue(), Some((2, Some(start))));
+        assert_eq!(queue.try_dequeue(), None);
+        assert_eq!(
+            queue.peek_deadline(),
+            Some(Some(start + Duration::from_secs(600)))
+        );
+
+        clock.advance(Duration::from_secs(600));
+        assert_eq!(
+            queue.try_dequeue(),
+            Some((1, Some(start + Duration::from_secs(600))))
+        );
+    }
+
+    #[test]
+    fn wheel_queue_holds_items_back_before_its_creation() {
+        // Ahead of real time, so that there is room to go back.
+        let clock = ManualClock::starting_at(Instant::now() + Duration::from_secs(3600));
+        let start = clock.now();
+        let queue = TimedQueue::builder()
+            .clock(clock.clone())
+            .timing_wheel(Duration::from_millis(10))
+            .build()
+            .unwrap();
+        let earlier = start - Duration::from_secs(60);
+        clock.set(earlier);
+        queue
+            .enqueue(1, Some(earlier + Duration::from_secs(30)))
+            .unwrap();
+        assert_eq!(queue.try_dequeue(), None);
+
+        clock.advance(Duration::from_secs(30));
+        assert_eq!(
+            queue.try_dequeue(),
+            Some((1, Some(earlier + Duration::from_secs(30))))
+        );
+    }
+}